@@ -0,0 +1,122 @@
+//! Ethereum addresses and their EIP-55 checksum encoding
+// https://eips.ethereum.org/EIPS/eip-55
+
+use std::fmt;
+use std::str::FromStr;
+
+use secp256k1::PublicKey;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::hash::keccak256;
+
+/// Address Error
+#[derive(Debug, thiserror::Error)]
+pub enum AddressError {
+    /// An address is 20 bytes
+    #[error("invalid address length {0}")]
+    InvalidLength(usize),
+
+    /// Mixed case address with a wrong EIP-55 checksum
+    #[error("invalid EIP-55 checksum")]
+    InvalidChecksum,
+
+    /// Hex encoding could not be decoded
+    #[error("invalid hex: {0}")]
+    Hex(#[from] hex::FromHexError),
+
+    /// Malformed public key
+    #[error("secp256k1 | {0}")]
+    PublicKey(#[from] secp256k1::Error),
+}
+
+/// 20 bytes Ethereum address, displayed with its EIP-55 checksum
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct EthAddress(pub [u8; 20]);
+
+impl EthAddress {
+    /// Address of a SEC1 encoded public key: the last 20 bytes of the
+    /// keccak256 of its uncompressed coordinates
+    pub fn from_public_key(public_key: &[u8]) -> Result<Self, AddressError> {
+        let public_key = PublicKey::from_slice(public_key)?;
+        let hash = keccak256(&public_key.serialize_uncompressed()[1..]);
+        // safe, a keccak256 digest is 32 bytes
+        Ok(EthAddress(hash[12..].try_into().unwrap()))
+    }
+
+    /// Parse 40 hex digits, with or without the `0x` prefix, ignoring the
+    /// checksum
+    pub fn from_hex_unchecked(s: &str) -> Result<Self, AddressError> {
+        let bytes = hex::decode(s.strip_prefix("0x").unwrap_or(s))?;
+        bytes
+            .try_into()
+            .map(EthAddress)
+            .map_err(|bytes: Vec<u8>| AddressError::InvalidLength(bytes.len()))
+    }
+
+    /// `0x` prefixed EIP-55 checksum encoding
+    pub fn to_checksum(self) -> String {
+        let lower = hex::encode(self.0);
+        let hash = keccak256(lower.as_bytes());
+        let checksummed: String = lower
+            .char_indices()
+            .map(|(i, c)| {
+                // uppercase letters whose nibble of the hash is at least 8
+                let nibble = (hash[i / 2] >> (4 * (1 - i % 2))) & 0x0f;
+                if nibble >= 8 {
+                    c.to_ascii_uppercase()
+                } else {
+                    c
+                }
+            })
+            .collect();
+        format!("0x{checksummed}")
+    }
+}
+
+impl From<[u8; 20]> for EthAddress {
+    fn from(bytes: [u8; 20]) -> Self {
+        EthAddress(bytes)
+    }
+}
+
+impl AsRef<[u8]> for EthAddress {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Display for EthAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_checksum())
+    }
+}
+
+impl FromStr for EthAddress {
+    type Err = AddressError;
+
+    /// Parse an address, validating its checksum when it is mixed case
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let address = Self::from_hex_unchecked(s)?;
+        let digits = s.strip_prefix("0x").unwrap_or(s);
+        let mixed_case = digits.chars().any(|c| c.is_ascii_lowercase())
+            && digits.chars().any(|c| c.is_ascii_uppercase());
+        if mixed_case && address.to_checksum()[2..] != *digits {
+            return Err(AddressError::InvalidChecksum);
+        }
+        Ok(address)
+    }
+}
+
+impl Serialize for EthAddress {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_checksum())
+    }
+}
+
+impl<'de> Deserialize<'de> for EthAddress {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
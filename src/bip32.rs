@@ -0,0 +1,169 @@
+//! BIP32 extended public keys, to derive non-hardened child addresses
+//! locally from a single device round trip
+// https://github.com/bitcoin/bips/blob/master/bip-0032.mediawiki
+
+use std::fmt;
+
+use hmac::{Hmac, Mac};
+use ledger_transport::Exchange;
+use ripemd::Ripemd160;
+use secp256k1::{PublicKey, Scalar, SECP256K1};
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::types::{DerivationPath, EthError};
+use crate::{Address, EthAddress, EthApp};
+
+/// Version bytes of a mainnet `xpub`
+const XPUB_VERSION: [u8; 4] = [0x04, 0x88, 0xb2, 0x1e];
+
+/// BIP32 Error
+#[derive(Debug, thiserror::Error)]
+pub enum Bip32Error {
+    /// Hardened children need the private key
+    #[error("cannot derive hardened child {0:#x} from a public key")]
+    Hardened(u32),
+
+    /// The derived key is invalid, the next index should be used instead
+    #[error("child {0} is not a valid key")]
+    InvalidChild(u32),
+
+    /// The device did not return a chain code
+    #[error("missing chain code")]
+    MissingChainCode,
+
+    /// Malformed public key
+    #[error("secp256k1 | {0}")]
+    Secp256k1(#[from] secp256k1::Error),
+}
+
+/// Public key and chain code of a BIP32 node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtendedPublicKey {
+    pub public_key: PublicKey,
+    pub chain_code: [u8; 32],
+    /// Number of derivations from the master key
+    pub depth: u8,
+    /// Fingerprint of the parent key, zeros for the master key
+    pub parent_fingerprint: [u8; 4],
+    /// Index of the key in its parent, with the hardened bit set
+    pub child_number: u32,
+}
+
+impl ExtendedPublicKey {
+    /// Extended key of the master node
+    pub fn new(public_key: PublicKey, chain_code: [u8; 32]) -> Self {
+        ExtendedPublicKey {
+            public_key,
+            chain_code,
+            depth: 0,
+            parent_fingerprint: [0; 4],
+            child_number: 0,
+        }
+    }
+
+    /// Extended key from an [`Address`] retrieved with its chain code, as the
+    /// master node
+    pub fn from_address(address: &Address) -> Result<Self, Bip32Error> {
+        let chain_code = address.chain_code.ok_or(Bip32Error::MissingChainCode)?;
+        let public_key = PublicKey::from_slice(&address.public_key)?;
+        Ok(Self::new(public_key, chain_code))
+    }
+
+    /// Derive the non-hardened child `index`
+    pub fn derive_child(&self, index: u32) -> Result<Self, Bip32Error> {
+        if index & DerivationPath::HARDENED != 0 {
+            return Err(Bip32Error::Hardened(index));
+        }
+        // safe, HMAC accepts keys of any size
+        let mut mac = Hmac::<Sha512>::new_from_slice(&self.chain_code).unwrap();
+        mac.update(&self.public_key.serialize());
+        mac.update(&index.to_be_bytes());
+        let i = mac.finalize().into_bytes();
+        let (il, ir) = i.split_at(32);
+
+        // safe, both halves are 32 bytes
+        let tweak = Scalar::from_be_bytes(il.try_into().unwrap())
+            .map_err(|_| Bip32Error::InvalidChild(index))?;
+        let public_key = self
+            .public_key
+            .add_exp_tweak(SECP256K1, &tweak)
+            .map_err(|_| Bip32Error::InvalidChild(index))?;
+
+        Ok(ExtendedPublicKey {
+            public_key,
+            chain_code: ir.try_into().unwrap(),
+            depth: self.depth.saturating_add(1),
+            parent_fingerprint: self.fingerprint(),
+            child_number: index,
+        })
+    }
+
+    /// Derive a chain of non-hardened children, e.g. `[0, i]` for the
+    /// `.../0/i` addresses of an account
+    pub fn derive(&self, indexes: &[u32]) -> Result<Self, Bip32Error> {
+        indexes
+            .iter()
+            .try_fold(*self, |key, index| key.derive_child(*index))
+    }
+
+    /// Ethereum address of the key
+    pub fn address(&self) -> EthAddress {
+        // safe, the key is valid
+        EthAddress::from_public_key(&self.public_key.serialize()).unwrap()
+    }
+
+    /// First 4 bytes of the HASH160 of the public key
+    pub fn fingerprint(&self) -> [u8; 4] {
+        let hash = Ripemd160::digest(Sha256::digest(self.public_key.serialize()));
+        // safe, a ripemd160 digest is 20 bytes
+        hash[..4].try_into().unwrap()
+    }
+
+    /// Base58Check `xpub` serialization
+    pub fn to_xpub(&self) -> String {
+        let mut data = Vec::with_capacity(78);
+        data.extend_from_slice(&XPUB_VERSION);
+        data.push(self.depth);
+        data.extend_from_slice(&self.parent_fingerprint);
+        data.extend_from_slice(&self.child_number.to_be_bytes());
+        data.extend_from_slice(&self.chain_code);
+        data.extend_from_slice(&self.public_key.serialize());
+        bs58::encode(data).with_check().into_string()
+    }
+}
+
+impl fmt::Display for ExtendedPublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_xpub())
+    }
+}
+
+impl<E> EthApp<E>
+where
+    E: Exchange + Send + Sync,
+    E::Error: std::error::Error,
+{
+    /// Retrieves the extended public key of `path`. The parent public key is
+    /// retrieved too, for the fingerprint of the `xpub`.
+    pub async fn extended_public_key(
+        &self,
+        path: impl Into<DerivationPath>,
+    ) -> Result<ExtendedPublicKey, EthError<E::Error>> {
+        let path = path.into();
+        let address = self.address(&path, None, Some(true)).await?;
+        let mut key = ExtendedPublicKey::from_address(&address)?;
+
+        let components = path.components();
+        if let [parent @ .., child_number] = components {
+            if !parent.is_empty() {
+                let parent = DerivationPath::new(parent.to_vec())?;
+                let parent = self.address(&parent, None, None).await?;
+                let parent = PublicKey::from_slice(&parent.public_key).map_err(Bip32Error::from)?;
+                key.parent_fingerprint = ExtendedPublicKey::new(parent, [0; 32]).fingerprint();
+            }
+            key.depth = components.len() as u8;
+            key.child_number = *child_number;
+        }
+        Ok(key)
+    }
+}
@@ -1,6 +1,13 @@
 pub(crate) mod get_address;
 pub(crate) mod get_app_configuration;
+pub(crate) mod get_eth2_public_key;
 pub(crate) mod provide_erc20_token_info;
+pub(crate) mod provide_nft_info;
+pub(crate) mod set_eth2_withdrawal_index;
+pub(crate) mod set_external_plugin;
+pub(crate) mod set_plugin;
+pub(crate) mod sign_eip712;
+pub(crate) mod sign_personal_message;
 pub(crate) mod sign_transaction;
 
 #[derive(Debug)]
@@ -9,5 +16,15 @@ pub enum InstructionCode {
     GetAddress = 0x02,
     SignTransaction = 0x04,
     GetAppConfiguration = 0x06,
+    SignPersonalMessage = 0x08,
     ProvideErc20TokenInfo = 0x0A,
+    SignEip712Message = 0x0C,
+    GetEth2PublicKey = 0x0E,
+    SetEth2WithdrawalIndex = 0x10,
+    SetExternalPlugin = 0x12,
+    ProvideNftInformation = 0x14,
+    SetPlugin = 0x16,
+    Eip712StructDef = 0x1A,
+    Eip712StructImpl = 0x1C,
+    Eip712Filtering = 0x1E,
 }
@@ -1,18 +1,36 @@
+use std::ops::Range;
+
 use ledger_transport::{APDUCommand, APDUErrorCode, Exchange};
 use ledger_zondax_generic::App;
 
+use crate::address::EthAddress;
 use crate::command::InstructionCode;
-use crate::types::{BIP44Path, EthError};
+use crate::types::{DerivationPath, EthError, PathScheme};
 use crate::{EthApp, LedgerAppError};
 
 #[derive(Debug)]
 pub struct Address {
     /// Secp256k1 pubkey bytes
     pub public_key: Vec<u8>,
-    /// Address bytes in raw UTF-8, without "0x" prefix
-    pub address: Vec<u8>,
-    /// Optional chain code bytes
-    pub chain_code: Option<Vec<u8>>,
+    /// Address, checked against the public key
+    pub address: EthAddress,
+    /// Chain code, when requested
+    pub chain_code: Option<[u8; 32]>,
+}
+
+/// Result of [`EthApp::verify_address`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerificationOutcome {
+    /// The user confirmed the expected address on the device
+    Confirmed,
+    /// The user rejected the address on the device
+    Rejected,
+    /// The device derived another address than the expected one, which was
+    /// not shown to the user
+    Mismatch {
+        /// Address derived by the device
+        device: EthAddress,
+    },
 }
 
 impl<E> EthApp<E>
@@ -23,11 +41,11 @@ where
     /// Retrieves the public key and address
     pub async fn address(
         &self,
-        path: &BIP44Path,
+        path: impl Into<DerivationPath>,
         enable_display: Option<bool>,
         enabled_chain_code: Option<bool>,
     ) -> Result<Address, EthError<E::Error>> {
-        let data = path.serialize_bip44();
+        let data = path.into().serialize();
         let p1 = enable_display.map_or(0, |v| v as u8);
         let p2 = enabled_chain_code.map_or(0, |v| v as u8);
 
@@ -82,17 +100,25 @@ where
         let address_end = address_start + address_len;
         let address = response_data
             .get(address_start..address_end)
-            .ok_or(EthError::MissingResponseData("address".into()))?
-            .to_vec();
+            .ok_or(EthError::MissingResponseData("address".into()))?;
+        // the device sends the address as hex, without the "0x" prefix
+        let address = EthAddress::from_hex_unchecked(&String::from_utf8_lossy(address))?;
+        let derived = EthAddress::from_public_key(&public_key)?;
+        if address != derived {
+            return Err(EthError::AddressMismatch {
+                reported: address,
+                derived,
+            });
+        }
 
+        // the chain code follows the address, without a length prefix
         let chain_code = if let Some(true) = enabled_chain_code {
-            let cc_start = address_end + 1;
-            let cc_end = address_start + address_len;
             Some(
                 response_data
-                    .get(cc_start..cc_end)
+                    .get(address_end..address_end + 32)
                     .ok_or(EthError::MissingResponseData("chain code".into()))?
-                    .to_vec(),
+                    .try_into() // safe due to get() range
+                    .unwrap(),
             )
         } else {
             None
@@ -103,4 +129,48 @@ where
             chain_code,
         })
     }
+
+    /// Retrieves the addresses of the accounts `indexes` of a path scheme,
+    /// e.g. to fill an account picker
+    pub async fn addresses(
+        &self,
+        scheme: &PathScheme,
+        indexes: Range<u32>,
+    ) -> Result<Vec<(DerivationPath, Address)>, EthError<E::Error>> {
+        let mut addresses = vec![];
+        for index in indexes {
+            let path = scheme.path(index)?;
+            let address = self.address(&path, None, None).await?;
+            addresses.push((path, address));
+        }
+        Ok(addresses)
+    }
+
+    /// Check that the address of `path` is the `expected` one, e.g. a stored
+    /// receive address, then display it on the device for the user to
+    /// confirm. A different address is reported without prompting the user.
+    pub async fn verify_address(
+        &self,
+        path: impl Into<DerivationPath>,
+        expected: EthAddress,
+    ) -> Result<VerificationOutcome, EthError<E::Error>> {
+        let path = path.into();
+        let device = self.address(&path, None, None).await?.address;
+        if device != expected {
+            return Ok(VerificationOutcome::Mismatch { device });
+        }
+
+        match self.address(&path, Some(true), None).await {
+            Ok(Address { address, .. }) if address == expected => {
+                Ok(VerificationOutcome::Confirmed)
+            }
+            Ok(Address { address, .. }) => Ok(VerificationOutcome::Mismatch { device: address }),
+            Err(EthError::Ledger(LedgerAppError::AppSpecific(code, _)))
+                if code == APDUErrorCode::ConditionsNotSatisfied as u16 =>
+            {
+                Ok(VerificationOutcome::Rejected)
+            }
+            Err(err) => Err(err),
+        }
+    }
 }
@@ -0,0 +1,58 @@
+use ledger_transport::{APDUCommand, Exchange};
+use ledger_zondax_generic::App;
+use sha2::{Digest, Sha256};
+
+use crate::command::InstructionCode;
+use crate::types::{DerivationPath, EthError};
+use crate::EthApp;
+
+#[derive(Debug)]
+pub struct Eth2PublicKey {
+    /// BLS12-381 public key bytes
+    pub public_key: [u8; 48],
+}
+
+impl Eth2PublicKey {
+    /// Prefix of BLS withdrawal credentials
+    pub const BLS_WITHDRAWAL_PREFIX: u8 = 0x00;
+
+    /// BLS withdrawal credentials of this key, as used in deposit data:
+    /// `0x00 || sha256(public_key)[1..]`
+    pub fn withdrawal_credentials(&self) -> [u8; 32] {
+        let mut credentials: [u8; 32] = Sha256::digest(self.public_key).into();
+        credentials[0] = Self::BLS_WITHDRAWAL_PREFIX;
+        credentials
+    }
+}
+
+impl<E> EthApp<E>
+where
+    E: Exchange + Send + Sync,
+    E::Error: std::error::Error,
+{
+    /// Retrieves the BLS12-381 public key of an EIP-2334 path, optionally
+    /// displaying it on the device for confirmation
+    // https://github.com/LedgerHQ/app-ethereum/blob/develop/doc/ethapp.adoc#get-eth2-public-key
+    pub async fn eth2_public_key(
+        &self,
+        path: impl Into<DerivationPath>,
+        display: bool,
+    ) -> Result<Eth2PublicKey, EthError<E::Error>> {
+        let command = APDUCommand {
+            cla: Self::CLA,
+            ins: InstructionCode::GetEth2PublicKey as _,
+            p1: display as u8,
+            p2: 0x00,
+            data: path.into().serialize(),
+        };
+
+        let response = self.exchange_checked(&command).await?;
+        let public_key = response
+            .data()
+            .get(..48)
+            .ok_or(EthError::MissingResponseData("eth2 public key".into()))?
+            .try_into() // safe due to get() range
+            .unwrap();
+        Ok(Eth2PublicKey { public_key })
+    }
+}
@@ -2,9 +2,67 @@ use ledger_transport::{APDUCommand, APDUErrorCode, Exchange};
 use ledger_zondax_generic::App;
 
 use crate::command::InstructionCode;
-use crate::types::EthError;
+use crate::types::{DescriptorError, DescriptorReader, EthError};
 use crate::{EthApp, LedgerAppError};
 
+/// Trusted description of an ERC 20 token, signed by Ledger
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Erc20TokenInfo {
+    /// Token ticker
+    pub ticker: String,
+    /// Address of the token contract
+    pub address: [u8; 20],
+    /// Number of decimals
+    pub decimals: u32,
+    /// Chain the contract is deployed on
+    pub chain_id: u32,
+    /// DER signature of the descriptor
+    pub signature: Vec<u8>,
+}
+
+impl Erc20TokenInfo {
+    /// Serialize an [`Erc20TokenInfo`] in the format used in the app
+    pub fn serialize(&self) -> Result<Vec<u8>, DescriptorError> {
+        let mut m = vec![];
+        m.push(u8::try_from(self.ticker.len()).map_err(|_| DescriptorError::TooLong("ticker"))?);
+        m.extend_from_slice(&self.signed_payload());
+        m.extend_from_slice(&self.signature);
+        Ok(m)
+    }
+
+    /// Part of the descriptor covered by the signature
+    pub(crate) fn signed_payload(&self) -> Vec<u8> {
+        let mut m = vec![];
+        m.extend_from_slice(self.ticker.as_bytes());
+        m.extend_from_slice(&self.address);
+        m.extend_from_slice(&self.decimals.to_be_bytes());
+        m.extend_from_slice(&self.chain_id.to_be_bytes());
+        m
+    }
+
+    /// Parse an [`Erc20TokenInfo`] from the format used in the app
+    pub fn parse(data: &[u8]) -> Result<Self, DescriptorError> {
+        let mut reader = DescriptorReader::new(data);
+        let ticker = reader.short_str("ticker")?;
+        let address = reader.address("address")?;
+        let decimals = reader.u32("decimals")?;
+        let chain_id = reader.u32("chain id")?;
+        // the signature is not length prefixed and runs until the end
+        let signature = reader.rest().to_vec();
+        if signature.is_empty() {
+            return Err(DescriptorError::Missing("signature"));
+        }
+
+        Ok(Erc20TokenInfo {
+            ticker,
+            address,
+            decimals,
+            chain_id,
+            signature,
+        })
+    }
+}
+
 impl<E> EthApp<E>
 where
     E: Exchange + Send + Sync,
@@ -20,6 +78,10 @@ where
     /// signed by the following secp256k1 public key
     /// 0482bbf2f34f367b2e5bc21847b6566f21f0976b22d3388a9a5e446ac62d25cf725b62a2555b2dd464a4da0ab2f4d506820543af1d242470b1b1a969a27578f353
     pub async fn provide_erc20_token_info(&self, data: &[u8]) -> Result<(), EthError<E::Error>> {
+        if let Some(cal_keys) = &self.cal_keys {
+            cal_keys.verify_erc20(&Erc20TokenInfo::parse(data)?)?;
+        }
+
         let command = APDUCommand {
             cla: Self::CLA,
             ins: InstructionCode::ProvideErc20TokenInfo as _,
@@ -49,4 +111,26 @@ where
         }
         Ok(())
     }
+
+    /// Same as [`EthApp::provide_erc20_token_info`] with a typed descriptor.
+    /// Returns the index of the token slot the device stored it in, `None` for
+    /// app versions that do not report it.
+    pub async fn provide_erc20(
+        &self,
+        info: &Erc20TokenInfo,
+    ) -> Result<Option<u8>, EthError<E::Error>> {
+        if let Some(cal_keys) = &self.cal_keys {
+            cal_keys.verify_erc20(info)?;
+        }
+
+        let command = APDUCommand {
+            cla: Self::CLA,
+            ins: InstructionCode::ProvideErc20TokenInfo as _,
+            p1: 0,
+            p2: 0,
+            data: info.serialize()?,
+        };
+        let response = self.exchange_checked(&command).await?;
+        Ok(response.data().first().copied())
+    }
 }
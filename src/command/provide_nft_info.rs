@@ -0,0 +1,111 @@
+use ledger_transport::{APDUCommand, Exchange};
+use ledger_zondax_generic::App;
+
+use crate::command::InstructionCode;
+use crate::types::{DescriptorError, DescriptorReader, EthError};
+use crate::EthApp;
+
+/// Trusted description of an NFT collection, signed by Ledger
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NftInfo {
+    /// Name of the collection
+    pub collection_name: String,
+    /// Address of the collection contract
+    pub contract_address: [u8; 20],
+    /// Chain the contract is deployed on
+    pub chain_id: u64,
+    /// Identifier of the key that signed the descriptor
+    pub key_id: u8,
+    /// Identifier of the signature algorithm
+    pub algorithm_id: u8,
+    /// DER signature of the descriptor
+    pub signature: Vec<u8>,
+}
+
+impl NftInfo {
+    /// Descriptor type
+    pub const TYPE: u8 = 0x01;
+    /// Descriptor version
+    pub const VERSION: u8 = 0x01;
+
+    /// Serialize an [`NftInfo`] in the format used in the app
+    pub fn serialize(&self) -> Result<Vec<u8>, DescriptorError> {
+        let mut m = self.signed_payload()?;
+        m.push(
+            u8::try_from(self.signature.len())
+                .map_err(|_| DescriptorError::TooLong("signature"))?,
+        );
+        m.extend_from_slice(&self.signature);
+        Ok(m)
+    }
+
+    /// Part of the descriptor covered by the signature
+    pub(crate) fn signed_payload(&self) -> Result<Vec<u8>, DescriptorError> {
+        let mut m = vec![Self::TYPE, Self::VERSION];
+        m.push(
+            u8::try_from(self.collection_name.len())
+                .map_err(|_| DescriptorError::TooLong("collection name"))?,
+        );
+        m.extend_from_slice(self.collection_name.as_bytes());
+        m.extend_from_slice(&self.contract_address);
+        m.extend_from_slice(&self.chain_id.to_be_bytes());
+        m.push(self.key_id);
+        m.push(self.algorithm_id);
+        Ok(m)
+    }
+
+    /// Parse an [`NftInfo`] from the format used in the app
+    pub fn parse(data: &[u8]) -> Result<Self, DescriptorError> {
+        let mut reader = DescriptorReader::new(data);
+        let descriptor_type = reader.u8("type")?;
+        if descriptor_type != Self::TYPE {
+            return Err(DescriptorError::Unsupported("type", descriptor_type));
+        }
+        let version = reader.u8("version")?;
+        if version != Self::VERSION {
+            return Err(DescriptorError::Unsupported("version", version));
+        }
+        let collection_name = reader.short_str("collection name")?;
+        let contract_address = reader.address("contract address")?;
+        let chain_id = reader.u64("chain id")?;
+        let key_id = reader.u8("key id")?;
+        let algorithm_id = reader.u8("algorithm id")?;
+        let signature = reader.short_bytes("signature")?.to_vec();
+        reader.finish()?;
+
+        Ok(NftInfo {
+            collection_name,
+            contract_address,
+            chain_id,
+            key_id,
+            algorithm_id,
+            signature,
+        })
+    }
+}
+
+impl<E> EthApp<E>
+where
+    E: Exchange + Send + Sync,
+    E::Error: std::error::Error,
+{
+    /// This command provides a trusted description of an NFT collection to
+    /// associate a contract address with a collection name. It shall be run
+    /// immediately before performing a transaction involving that contract.
+    // https://github.com/LedgerHQ/app-ethereum/blob/develop/doc/ethapp.adoc#provide-nft-information
+    pub async fn provide_nft_info(&self, info: &NftInfo) -> Result<(), EthError<E::Error>> {
+        if let Some(cal_keys) = &self.cal_keys {
+            cal_keys.verify_nft(info)?;
+        }
+
+        let command = APDUCommand {
+            cla: Self::CLA,
+            ins: InstructionCode::ProvideNftInformation as _,
+            p1: 0x00,
+            p2: 0x00,
+            data: info.serialize()?,
+        };
+        self.exchange_checked(&command).await?;
+        Ok(())
+    }
+}
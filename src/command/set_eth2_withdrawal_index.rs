@@ -0,0 +1,31 @@
+use byteorder::{BigEndian, WriteBytesExt};
+use ledger_transport::{APDUCommand, Exchange};
+use ledger_zondax_generic::App;
+
+use crate::command::InstructionCode;
+use crate::types::EthError;
+use crate::EthApp;
+
+impl<E> EthApp<E>
+where
+    E: Exchange + Send + Sync,
+    E::Error: std::error::Error,
+{
+    /// Sets the index of the withdrawal key used as withdrawal credentials in
+    /// an ETH2 deposit contract call signature
+    // https://github.com/LedgerHQ/app-ethereum/blob/develop/doc/ethapp.adoc#set-eth2-withdrawal-index
+    pub async fn set_eth2_withdrawal_index(&self, index: u32) -> Result<(), EthError<E::Error>> {
+        let mut data = vec![];
+        data.write_u32::<BigEndian>(index).unwrap();
+
+        let command = APDUCommand {
+            cla: Self::CLA,
+            ins: InstructionCode::SetEth2WithdrawalIndex as _,
+            p1: 0x00,
+            p2: 0x00,
+            data,
+        };
+        self.exchange_checked(&command).await?;
+        Ok(())
+    }
+}
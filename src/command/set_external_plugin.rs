@@ -0,0 +1,66 @@
+use ledger_transport::{APDUCommand, Exchange};
+use ledger_zondax_generic::App;
+
+use crate::command::InstructionCode;
+use crate::types::{DescriptorError, EthError, ExternalPluginData};
+use crate::EthApp;
+
+impl ExternalPluginData {
+    /// Build the descriptor of an external plugin handling calls to `selector`
+    /// on `contract_address`, signed by Ledger
+    pub fn new(
+        plugin_name: &str,
+        contract_address: [u8; 20],
+        selector: [u8; 4],
+        signature: &[u8],
+    ) -> Result<Self, DescriptorError> {
+        let mut payload = vec![];
+        payload.push(
+            u8::try_from(plugin_name.len()).map_err(|_| DescriptorError::TooLong("plugin name"))?,
+        );
+        payload.extend_from_slice(plugin_name.as_bytes());
+        payload.extend_from_slice(&contract_address);
+        payload.extend_from_slice(&selector);
+
+        Ok(ExternalPluginData {
+            payload: hex::encode(payload),
+            signature: hex::encode(signature),
+        })
+    }
+
+    /// Serialize an [`ExternalPluginData`] in the format used in the app
+    pub fn serialize(&self) -> Result<Vec<u8>, DescriptorError> {
+        let mut m = hex::decode(&self.payload)?;
+        m.extend_from_slice(&hex::decode(&self.signature)?);
+        Ok(m)
+    }
+}
+
+impl<E> EthApp<E>
+where
+    E: Exchange + Send + Sync,
+    E::Error: std::error::Error,
+{
+    /// This command provides the name of a trusted binding of a plugin with a
+    /// contract address and a supported method selector. It shall be run
+    /// immediately before performing a transaction involving that contract.
+    // https://github.com/LedgerHQ/app-ethereum/blob/develop/doc/ethapp.adoc#set-external-plugin
+    pub async fn set_external_plugin(
+        &self,
+        data: &ExternalPluginData,
+    ) -> Result<(), EthError<E::Error>> {
+        if let Some(cal_keys) = &self.cal_keys {
+            cal_keys.verify_external_plugin(data)?;
+        }
+
+        let command = APDUCommand {
+            cla: Self::CLA,
+            ins: InstructionCode::SetExternalPlugin as _,
+            p1: 0x00,
+            p2: 0x00,
+            data: data.serialize()?,
+        };
+        self.exchange_checked(&command).await?;
+        Ok(())
+    }
+}
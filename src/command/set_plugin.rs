@@ -0,0 +1,124 @@
+use ledger_transport::{APDUCommand, Exchange};
+use ledger_zondax_generic::App;
+
+use crate::command::InstructionCode;
+use crate::types::{DescriptorError, DescriptorReader, EthError};
+use crate::EthApp;
+
+/// Ledger-signed binding of an internal plugin (e.g. `ERC721`, `ERC1155`) to a
+/// contract method
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginInfo {
+    /// Descriptor type
+    pub plugin_type: u8,
+    /// Descriptor version
+    pub version: u8,
+    /// Name of the plugin
+    pub plugin_name: String,
+    /// Address of the contract
+    pub contract_address: [u8; 20],
+    /// Method selector handled by the plugin
+    pub selector: [u8; 4],
+    /// Chain the contract is deployed on
+    pub chain_id: u64,
+    /// Identifier of the key that signed the descriptor
+    pub key_id: u8,
+    /// Identifier of the signature algorithm
+    pub algorithm_id: u8,
+    /// DER signature of the descriptor
+    pub signature: Vec<u8>,
+}
+
+impl PluginInfo {
+    /// Descriptor type supported by the app
+    pub const TYPE: u8 = 0x01;
+    /// Descriptor version supported by the app
+    pub const VERSION: u8 = 0x01;
+
+    /// Serialize a [`PluginInfo`] in the format used in the app
+    pub fn serialize(&self) -> Result<Vec<u8>, DescriptorError> {
+        let mut m = self.signed_payload()?;
+        m.push(
+            u8::try_from(self.signature.len())
+                .map_err(|_| DescriptorError::TooLong("signature"))?,
+        );
+        m.extend_from_slice(&self.signature);
+        Ok(m)
+    }
+
+    /// Part of the descriptor covered by the signature
+    pub(crate) fn signed_payload(&self) -> Result<Vec<u8>, DescriptorError> {
+        let mut m = vec![self.plugin_type, self.version];
+        m.push(
+            u8::try_from(self.plugin_name.len())
+                .map_err(|_| DescriptorError::TooLong("plugin name"))?,
+        );
+        m.extend_from_slice(self.plugin_name.as_bytes());
+        m.extend_from_slice(&self.contract_address);
+        m.extend_from_slice(&self.selector);
+        m.extend_from_slice(&self.chain_id.to_be_bytes());
+        m.push(self.key_id);
+        m.push(self.algorithm_id);
+        Ok(m)
+    }
+
+    /// Parse a [`PluginInfo`] from the format used in the app
+    pub fn parse(data: &[u8]) -> Result<Self, DescriptorError> {
+        let mut reader = DescriptorReader::new(data);
+        let plugin_type = reader.u8("type")?;
+        if plugin_type != Self::TYPE {
+            return Err(DescriptorError::Unsupported("type", plugin_type));
+        }
+        let version = reader.u8("version")?;
+        if version != Self::VERSION {
+            return Err(DescriptorError::Unsupported("version", version));
+        }
+        let plugin_name = reader.short_str("plugin name")?;
+        let contract_address = reader.address("contract address")?;
+        let selector = reader.bytes(4, "selector")?.try_into().unwrap();
+        let chain_id = reader.u64("chain id")?;
+        let key_id = reader.u8("key id")?;
+        let algorithm_id = reader.u8("algorithm id")?;
+        let signature = reader.short_bytes("signature")?.to_vec();
+        reader.finish()?;
+
+        Ok(PluginInfo {
+            plugin_type,
+            version,
+            plugin_name,
+            contract_address,
+            selector,
+            chain_id,
+            key_id,
+            algorithm_id,
+            signature,
+        })
+    }
+}
+
+impl<E> EthApp<E>
+where
+    E: Exchange + Send + Sync,
+    E::Error: std::error::Error,
+{
+    /// This command provides the name of a trusted binding of a plugin with a
+    /// contract address and a supported method selector, for plugins that are
+    /// part of the app. It shall be run immediately before performing a
+    /// transaction involving that contract.
+    // https://github.com/LedgerHQ/app-ethereum/blob/develop/doc/ethapp.adoc#set-plugin
+    pub async fn set_plugin(&self, info: &PluginInfo) -> Result<(), EthError<E::Error>> {
+        if let Some(cal_keys) = &self.cal_keys {
+            cal_keys.verify_plugin(info)?;
+        }
+
+        let command = APDUCommand {
+            cla: Self::CLA,
+            ins: InstructionCode::SetPlugin as _,
+            p1: 0x00,
+            p2: 0x00,
+            data: info.serialize()?,
+        };
+        self.exchange_checked(&command).await?;
+        Ok(())
+    }
+}
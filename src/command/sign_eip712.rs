@@ -0,0 +1,189 @@
+use byteorder::{BigEndian, WriteBytesExt};
+use ledger_transport::{APDUCommand, Exchange};
+use ledger_zondax_generic::App;
+
+use crate::command::InstructionCode;
+use crate::eip712::{Eip712Apdu, Eip712Filters, TypedData};
+use crate::recovery::eip712_hash;
+use crate::types::{DerivationPath, EthError};
+use crate::{EthApp, LedgerAppError, Signature};
+
+/// P2 of SIGN ETH EIP 712 selecting the signing mode
+#[repr(u8)]
+pub(crate) enum Eip712Mode {
+    /// Domain separator and message hash are computed by the host (v0)
+    Hashed = 0x00,
+    /// Domain and message are streamed with the EIP712 STRUCT APDUs
+    Full = 0x01,
+}
+
+/// P2 of EIP712 SEND STRUCT DEFINITION / IMPLEMENTATION
+#[repr(u8)]
+enum StructP2 {
+    Name = 0x00,
+    Array = 0x0F,
+    Field = 0xFF,
+}
+
+/// P1 of EIP712 SEND STRUCT IMPLEMENTATION
+#[repr(u8)]
+enum StructImplP1 {
+    Complete = 0x00,
+    Partial = 0x01,
+}
+
+impl<E> EthApp<E>
+where
+    E: Exchange + Send + Sync,
+    E::Error: std::error::Error,
+{
+    /// Sign an EIP-712 message using the pre-hashed (v0) mode, where the
+    /// device only displays the domain separator and message hash
+    // https://github.com/LedgerHQ/app-ethereum/blob/develop/doc/ethapp.adoc#sign-eth-eip-712
+    pub async fn sign_eip712_hashed(
+        &self,
+        path: impl Into<DerivationPath>,
+        domain_separator: [u8; 32],
+        message_hash: [u8; 32],
+    ) -> Result<Signature, EthError<E::Error>> {
+        let path = path.into();
+        let mut data = path.serialize();
+        data.extend_from_slice(&domain_separator);
+        data.extend_from_slice(&message_hash);
+
+        let command = APDUCommand {
+            cla: Self::CLA,
+            ins: InstructionCode::SignEip712Message as _,
+            p1: 0x00,
+            p2: Eip712Mode::Hashed as u8,
+            data,
+        };
+
+        let response = self
+            .transport
+            .exchange(&command)
+            .await
+            .map_err(LedgerAppError::TransportError)?;
+        let signature = Signature::from_answer(&response)?;
+        let hash = eip712_hash(&domain_separator, &message_hash);
+        self.check_signature(&path, hash, &signature).await?;
+        Ok(signature)
+    }
+
+    /// Sign `eth_signTypedData_v4` typed data in full mode: every struct
+    /// definition is sent, then the domain and message values are streamed so
+    /// the device can clear-sign them. Without filters, every field is shown
+    /// raw.
+    pub async fn sign_eip712(
+        &self,
+        path: impl Into<DerivationPath>,
+        typed_data: &TypedData,
+        filters: Option<&Eip712Filters>,
+    ) -> Result<Signature, EthError<E::Error>> {
+        // encode and validate everything before talking to the device
+        let path = path.into();
+        let definitions = typed_data.struct_definitions()?;
+        let implementations = typed_data.struct_implementations(filters)?;
+        let hash = typed_data.signing_hash()?;
+
+        for apdu in definitions.iter().chain(&implementations) {
+            self.send_eip712_apdu(apdu).await?;
+        }
+
+        let command = APDUCommand {
+            cla: Self::CLA,
+            ins: InstructionCode::SignEip712Message as _,
+            p1: 0x00,
+            p2: Eip712Mode::Full as u8,
+            data: path.serialize(),
+        };
+
+        let response = self
+            .transport
+            .exchange(&command)
+            .await
+            .map_err(LedgerAppError::TransportError)?;
+        let signature = Signature::from_answer(&response)?;
+        self.check_signature(&path, hash, &signature).await?;
+        Ok(signature)
+    }
+
+    /// Send a single EIP712 STRUCT DEFINITION / IMPLEMENTATION / FILTERING
+    /// payload
+    pub(crate) async fn send_eip712_apdu(
+        &self,
+        apdu: &Eip712Apdu,
+    ) -> Result<(), EthError<E::Error>> {
+        let (ins, p2, data) = match apdu {
+            Eip712Apdu::StructDefName(name) => (
+                InstructionCode::Eip712StructDef,
+                StructP2::Name,
+                name.as_bytes().to_vec(),
+            ),
+            Eip712Apdu::StructDefField(field) => (
+                InstructionCode::Eip712StructDef,
+                StructP2::Field,
+                field.clone(),
+            ),
+            Eip712Apdu::StructImplRoot(name) => (
+                InstructionCode::Eip712StructImpl,
+                StructP2::Name,
+                name.as_bytes().to_vec(),
+            ),
+            Eip712Apdu::StructImplArray(size) => (
+                InstructionCode::Eip712StructImpl,
+                StructP2::Array,
+                vec![*size],
+            ),
+            Eip712Apdu::Filter {
+                discarded,
+                p2,
+                data,
+            } => {
+                let command = APDUCommand {
+                    cla: Self::CLA,
+                    ins: InstructionCode::Eip712Filtering as _,
+                    p1: *discarded as u8,
+                    p2: *p2,
+                    data: data.clone(),
+                };
+                self.exchange_checked(&command).await?;
+                return Ok(());
+            }
+            Eip712Apdu::StructImplField(value) => {
+                // values are length prefixed and may span several APDUs
+                let mut data = vec![];
+                data.write_u16::<BigEndian>(value.len() as u16).unwrap();
+                data.extend_from_slice(value);
+
+                let chunks = data.chunks(255).collect::<Vec<_>>();
+                for (i, chunk) in chunks.iter().enumerate() {
+                    let p1 = if i + 1 < chunks.len() {
+                        StructImplP1::Partial
+                    } else {
+                        StructImplP1::Complete
+                    };
+                    let command = APDUCommand {
+                        cla: Self::CLA,
+                        ins: InstructionCode::Eip712StructImpl as _,
+                        p1: p1 as u8,
+                        p2: StructP2::Field as u8,
+                        data: chunk.to_vec(),
+                    };
+                    self.exchange_checked(&command).await?;
+                }
+                return Ok(());
+            }
+        };
+
+        let command = APDUCommand {
+            cla: Self::CLA,
+            ins: ins as _,
+            p1: 0x00,
+            p2: p2 as u8,
+            data,
+        };
+        self.exchange_checked(&command).await?;
+        Ok(())
+    }
+}
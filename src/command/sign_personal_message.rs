@@ -0,0 +1,46 @@
+use byteorder::{BigEndian, WriteBytesExt};
+use ledger_transport::{APDUCommand, Exchange};
+use ledger_zondax_generic::App;
+
+use crate::command::InstructionCode;
+use crate::recovery::eip191_hash;
+use crate::types::{ChunkPayloadType, DerivationPath, EthError};
+use crate::{EthApp, Signature};
+
+impl<E> EthApp<E>
+where
+    E: Exchange + Send + Sync,
+    E::Error: std::error::Error,
+{
+    /// Sign a message following the personal_sign specification
+    /// (EIP-191 version 0x45). The device prepends the
+    /// `"\x19Ethereum Signed Message:\n" + len(message)` prefix itself.
+    // https://github.com/LedgerHQ/app-ethereum/blob/develop/doc/ethapp.adoc#sign-eth-personal-message
+    pub async fn sign_personal_message(
+        &self,
+        path: impl Into<DerivationPath>,
+        message: &[u8],
+    ) -> Result<Signature, EthError<E::Error>> {
+        let path = path.into();
+        let message_len = u32::try_from(message.len())
+            .map_err(|_| EthError::Other("personal message is too long".into()))?;
+
+        let mut data = path.serialize();
+        data.write_u32::<BigEndian>(message_len).unwrap();
+        data.extend_from_slice(message);
+
+        let command = APDUCommand {
+            cla: Self::CLA,
+            ins: InstructionCode::SignPersonalMessage as _,
+            p1: ChunkPayloadType::First as u8,
+            p2: 0x00,
+            data,
+        };
+
+        let response = self.send_chunks(command).await?;
+        let signature = Signature::from_answer(&response)?;
+        self.check_signature(&path, eip191_hash(message), &signature)
+            .await?;
+        Ok(signature)
+    }
+}
@@ -1,89 +1,170 @@
-use ledger_transport::{APDUCommand, APDUErrorCode, Exchange};
-use ledger_zondax_generic::{App, LedgerAppError};
+use ledger_transport::{APDUCommand, Exchange};
+use ledger_zondax_generic::App;
 
 use crate::command::InstructionCode;
-use crate::types::{BIP44Path, ChunkPayloadType, EthError, LedgerEthTransactionResolution};
-use crate::EthApp;
-
-#[derive(Debug)]
-pub struct Signature {
-    pub v: u8,
-    pub r: [u8; 32],
-    pub s: [u8; 32],
-}
+use crate::transaction::{self, TypedTx};
+use crate::types::{
+    ChunkPayloadType, DerivationPath, DescriptorError, EthError, LedgerEthTransactionResolution,
+};
+use crate::{Erc20TokenInfo, EthApp, NftInfo, PluginInfo, Signature};
+
+/// Number of token slots of the app, shared by ERC 20 and NFT descriptors
+pub const MAX_TOKEN_SLOTS: usize = 2;
 
 impl<E> EthApp<E>
 where
     E: Exchange + Send + Sync,
     E::Error: std::error::Error,
 {
-    /// Sign a transaction
+    /// Sign a transaction, providing the descriptors of `resolution` first so
+    /// the device can clear-sign it. The returned `v` is recomputed from the
+    /// chain id of the transaction, as the device only returns its low byte.
+    ///
+    /// Errors of the signing exchange, e.g. a rejection on the device, are
+    /// wrapped in [`EthError::Signing`] along with a summary of the
+    /// transaction, when it can be decoded. Errors providing the resolution
+    /// are returned as is.
     pub async fn sign(
         &self,
-        path: &BIP44Path,
+        path: impl Into<DerivationPath>,
+        raw_tx: &[u8],
+        resolution: Option<LedgerEthTransactionResolution>,
+    ) -> Result<Signature, EthError<E::Error>> {
+        let path = path.into();
+        let (tx_type, chain_id) = transaction::type_and_chain_id(raw_tx)?;
+        let tail_offset = transaction::eip155_tail_offset(raw_tx)?;
+
+        if let Some(resolution) = &resolution {
+            self.provide_resolution(resolution).await?;
+        }
+
+        let result = self.sign_raw(&path, raw_tx, tail_offset).await;
+        let mut signature = match (result, transaction::decode(raw_tx)) {
+            (Ok(signature), _) => signature,
+            (Err(error), Ok(tx)) => {
+                return Err(EthError::Signing {
+                    summary: Box::new(tx.summary()),
+                    error: Box::new(error),
+                })
+            }
+            (Err(error), Err(_)) => return Err(error),
+        };
+        let y_parity = transaction::y_parity(tx_type, chain_id, signature.v)?;
+        signature.v = transaction::full_v(tx_type, chain_id, y_parity)?;
+        self.check_signature(&path, transaction::signing_hash(raw_tx), &signature)
+            .await?;
+        Ok(signature)
+    }
+
+    /// Send the transaction, returning the signature as is
+    async fn sign_raw(
+        &self,
+        path: &DerivationPath,
         raw_tx: &[u8],
-        // TODO: come back to this later and see if we can resolve txns instead of blind signing
-        _resolution: Option<LedgerEthTransactionResolution>,
+        tail_offset: Option<usize>,
     ) -> Result<Signature, EthError<E::Error>> {
         let mut data = vec![];
-        let path = path.serialize_bip44();
+        let path = path.serialize();
         data.extend_from_slice(&path);
         data.extend_from_slice(raw_tx);
+        let chunks = transaction::sign_chunks(&data, tail_offset.map(|o| path.len() + o));
 
         let command = APDUCommand {
             cla: Self::CLA,
             ins: InstructionCode::SignTransaction as _,
             p1: ChunkPayloadType::First as u8,
             p2: 0x00,
-            data,
+            data: vec![],
         };
 
-        let response = self.send_chunks(command).await?;
+        let response = self.send_chunk_list(command, chunks).await?;
+        Signature::from_answer(&response)
+    }
 
-        let response_data = response.data();
-        match response.error_code() {
-            Ok(APDUErrorCode::NoError) if response_data.is_empty() => {
-                return Err(EthError::Ledger(LedgerAppError::NoSignature))
-            }
-            // Last response should contain the answer
-            Ok(APDUErrorCode::NoError) if response_data.len() < 3 => {
-                return Err(EthError::Ledger(LedgerAppError::InvalidSignature))
-            }
-            Ok(APDUErrorCode::NoError) => {}
-            Ok(err) => {
-                return Err(EthError::Ledger(LedgerAppError::AppSpecific(
-                    err as _,
-                    err.description(),
-                )))
-            }
-            Err(err) => {
-                return Err(EthError::Ledger(LedgerAppError::AppSpecific(
-                    err,
-                    "[APDU_ERROR] Unknown".to_string(),
-                )))
+    /// Sign a typed transaction
+    pub async fn sign_tx(
+        &self,
+        path: impl Into<DerivationPath>,
+        tx: &TypedTx,
+    ) -> Result<Signature, EthError<E::Error>> {
+        self.sign(path, &tx.encode_unsigned(), None).await
+    }
+}
+
+impl<E> EthApp<E>
+where
+    E: Exchange + Send + Sync,
+    E::Error: std::error::Error,
+{
+    /// Provide every descriptor of a transaction resolution, in the order the
+    /// app expects them: ERC 20 tokens, NFTs, external plugins, then plugins
+    pub async fn provide_resolution(
+        &self,
+        resolution: &LedgerEthTransactionResolution,
+    ) -> Result<(), EthError<E::Error>> {
+        let erc20_tokens = resolution.erc20_tokens.len();
+        if erc20_tokens + resolution.nfts.len() > MAX_TOKEN_SLOTS {
+            // later descriptors would silently evict earlier ones, so name the
+            // first one without a slot
+            let descriptor = match MAX_TOKEN_SLOTS.checked_sub(erc20_tokens) {
+                Some(i) => format!("NFT #{i}"),
+                None => format!("ERC 20 token #{MAX_TOKEN_SLOTS}"),
+            };
+            return Err(EthError::ResolutionRejected {
+                descriptor,
+                reason: format!("the device only has {MAX_TOKEN_SLOTS} token slots"),
+            });
+        }
+
+        // name the descriptor an error happened on
+        let rejected = |descriptor: String, err: EthError<E::Error>| -> EthError<E::Error> {
+            EthError::ResolutionRejected {
+                descriptor,
+                reason: err.to_string(),
             }
+        };
+
+        for (i, data) in resolution.erc20_tokens.iter().enumerate() {
+            let descriptor = format!("ERC 20 token #{i}");
+            let info = parse_hex(data, Erc20TokenInfo::parse)
+                .map_err(|err| rejected(descriptor.clone(), err.into()))?;
+            self.provide_erc20(&info)
+                .await
+                .map_err(|err| rejected(format!("{descriptor} ({})", info.ticker), err))?;
+        }
+
+        for (i, data) in resolution.nfts.iter().enumerate() {
+            let descriptor = format!("NFT #{i}");
+            let info = parse_hex(data, NftInfo::parse)
+                .map_err(|err| rejected(descriptor.clone(), err.into()))?;
+            self.provide_nft_info(&info)
+                .await
+                .map_err(|err| rejected(format!("{descriptor} ({})", info.collection_name), err))?;
+        }
+
+        for (i, data) in resolution.external_plugins.iter().enumerate() {
+            self.set_external_plugin(data)
+                .await
+                .map_err(|err| rejected(format!("external plugin #{i}"), err))?;
         }
 
-        let v = response_data
-            .first()
-            .ok_or(EthError::MissingResponseData(
-                "signature v component".into(),
-            ))?
-            .to_owned();
-        let r = response_data
-            .get(1..33)
-            .ok_or(EthError::MissingResponseData(
-                "signature r component".into(),
-            ))?
-            .try_into() // safe due to get() range
-            .unwrap();
-        let s = response_data
-            .get(33..65)
-            .ok_or(EthError::MissingResponseData(
-                "signature s component".into(),
-            ))?
-            .try_into() // safe due to get() range
-            .unwrap();
-        Ok(Signature { v, r, s })
+        for (i, data) in resolution.plugin.iter().enumerate() {
+            let descriptor = format!("plugin #{i}");
+            let info = parse_hex(data, PluginInfo::parse)
+                .map_err(|err| rejected(descriptor.clone(), err.into()))?;
+            self.set_plugin(&info)
+                .await
+                .map_err(|err| rejected(format!("{descriptor} ({})", info.plugin_name), err))?;
+        }
+
+        Ok(())
     }
 }
+
+/// Decode a hex format descriptor
+fn parse_hex<T>(
+    data: &str,
+    parse: fn(&[u8]) -> Result<T, DescriptorError>,
+) -> Result<T, DescriptorError> {
+    parse(&hex::decode(data)?)
+}
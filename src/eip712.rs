@@ -0,0 +1,990 @@
+//! EIP-712 typed data, as accepted by `eth_signTypedData_v4`, and its
+//! encoding into the EIP712 STRUCT DEFINITION / IMPLEMENTATION payloads
+// https://github.com/LedgerHQ/app-ethereum/blob/develop/doc/ethapp.adoc#eip712-send-struct-definition
+// https://github.com/LedgerHQ/app-ethereum/blob/develop/doc/ethapp.adoc#eip712-send-struct-implementation
+// https://github.com/LedgerHQ/app-ethereum/blob/develop/doc/ethapp.adoc#eip712-filtering
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::hash::keccak256;
+use crate::recovery::eip712_hash;
+
+/// Name of the domain struct, always implemented first
+pub const DOMAIN_TYPE: &str = "EIP712Domain";
+
+/// EIP-712 Error
+#[derive(Debug, thiserror::Error)]
+pub enum Eip712Error {
+    /// A referenced struct type is missing from `types`
+    #[error("Unknown struct type: {0}")]
+    UnknownType(String),
+
+    /// A field type could not be parsed
+    #[error("Invalid field type: {0}")]
+    InvalidType(String),
+
+    /// A domain or message value does not match its declared type
+    #[error("Invalid value for {path}: {reason}")]
+    InvalidValue { path: String, reason: String },
+
+    /// A name, value or array is too long to be sent to the device
+    #[error("{0} is too long to be sent to the device")]
+    TooLong(String),
+
+    /// A filter path does not match a field of the primary type
+    #[error("Invalid filter path: {0}")]
+    InvalidFilterPath(String),
+}
+
+/// Field of a struct type
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Eip712Field {
+    /// Field name
+    pub name: String,
+    /// Solidity type of the field, e.g. `uint256[]` or `Person`
+    #[serde(rename = "type")]
+    pub r#type: String,
+}
+
+/// Typed data document of `eth_signTypedData_v4`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypedData {
+    /// Struct types, including [`DOMAIN_TYPE`]
+    pub types: BTreeMap<String, Vec<Eip712Field>>,
+    /// Name of the struct type of `message`
+    pub primary_type: String,
+    /// Domain values
+    pub domain: Value,
+    /// Message values
+    pub message: Value,
+}
+
+impl FromStr for TypedData {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
+
+/// Base type of a field, without array levels
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BaseType {
+    /// Another struct type
+    Custom(String),
+    /// Signed integer of the given size in bytes
+    Int(u8),
+    /// Unsigned integer of the given size in bytes
+    Uint(u8),
+    Address,
+    Bool,
+    String,
+    /// `bytes1` to `bytes32`
+    FixedBytes(u8),
+    /// `bytes`
+    DynamicBytes,
+}
+
+/// Array level of a field type, in the order they appear in the type name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArrayLevel {
+    Dynamic,
+    Fixed(u8),
+}
+
+/// Parsed field type, e.g. `uint256[2][]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldType {
+    pub base: BaseType,
+    pub array_levels: Vec<ArrayLevel>,
+}
+
+impl FromStr for FieldType {
+    type Err = Eip712Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Eip712Error::InvalidType(s.to_string());
+
+        let (base, mut rest) = s.split_at(s.find('[').unwrap_or(s.len()));
+        let mut array_levels = vec![];
+        while !rest.is_empty() {
+            let end = rest.find(']').ok_or_else(invalid)?;
+            let size = rest
+                .get(1..end)
+                .filter(|_| rest.starts_with('['))
+                .ok_or_else(invalid)?;
+            array_levels.push(if size.is_empty() {
+                ArrayLevel::Dynamic
+            } else {
+                ArrayLevel::Fixed(size.parse().map_err(|_| invalid())?)
+            });
+            rest = &rest[end + 1..];
+        }
+
+        let base = match base {
+            "" => return Err(invalid()),
+            "address" => BaseType::Address,
+            "bool" => BaseType::Bool,
+            "string" => BaseType::String,
+            "bytes" => BaseType::DynamicBytes,
+            "int" => BaseType::Int(32),
+            "uint" => BaseType::Uint(32),
+            _ => {
+                if let Some(bits) = base.strip_prefix("uint").filter(|b| is_number(b)) {
+                    BaseType::Uint(integer_size(bits).ok_or_else(invalid)?)
+                } else if let Some(bits) = base.strip_prefix("int").filter(|b| is_number(b)) {
+                    BaseType::Int(integer_size(bits).ok_or_else(invalid)?)
+                } else if let Some(len) = base.strip_prefix("bytes").filter(|b| is_number(b)) {
+                    match len.parse() {
+                        Ok(len @ 1..=32) => BaseType::FixedBytes(len),
+                        _ => return Err(invalid()),
+                    }
+                } else {
+                    BaseType::Custom(base.to_string())
+                }
+            }
+        };
+
+        Ok(FieldType { base, array_levels })
+    }
+}
+
+fn is_number(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Size in bytes of an `intN`/`uintN` type
+fn integer_size(bits: &str) -> Option<u8> {
+    let bits: u16 = bits.parse().ok()?;
+    (bits & 7 == 0 && (8..=256).contains(&bits)).then_some((bits / 8) as u8)
+}
+
+impl FieldType {
+    /// Serialize the type description of a struct field definition
+    pub(crate) fn serialize(&self) -> Result<Vec<u8>, Eip712Error> {
+        let (type_id, size, name) = match &self.base {
+            BaseType::Custom(name) => (0, None, Some(name)),
+            BaseType::Int(size) => (1, Some(*size), None),
+            BaseType::Uint(size) => (2, Some(*size), None),
+            BaseType::Address => (3, None, None),
+            BaseType::Bool => (4, None, None),
+            BaseType::String => (5, None, None),
+            BaseType::FixedBytes(size) => (6, Some(*size), None),
+            BaseType::DynamicBytes => (7, None, None),
+        };
+
+        let mut type_desc = type_id;
+        if !self.array_levels.is_empty() {
+            type_desc |= 0x80;
+        }
+        if size.is_some() {
+            type_desc |= 0x40;
+        }
+
+        let mut m = vec![type_desc];
+        if let Some(name) = name {
+            push_short(&mut m, name.as_bytes(), name)?;
+        }
+        if let Some(size) = size {
+            m.push(size);
+        }
+        if !self.array_levels.is_empty() {
+            m.push(short_len(self.array_levels.len(), "array levels")?);
+            for level in &self.array_levels {
+                match level {
+                    ArrayLevel::Dynamic => m.push(0),
+                    ArrayLevel::Fixed(size) => m.extend_from_slice(&[1, *size]),
+                }
+            }
+        }
+        Ok(m)
+    }
+}
+
+/// Ledger-signed filters, shown in place of the raw message fields
+#[derive(Debug, Clone)]
+pub struct Eip712Filters {
+    /// Message info, displayed as the message name
+    pub message_info: MessageInfoFilter,
+    /// Field filters, keyed by path in the primary type (e.g. `to.wallets.[]`)
+    pub fields: Vec<FieldFilter>,
+}
+
+/// Message info filter
+#[derive(Debug, Clone)]
+pub struct MessageInfoFilter {
+    /// Name displayed for the whole message
+    pub display_name: String,
+    /// Ledger signature of the filter
+    pub signature: Vec<u8>,
+}
+
+/// Filter applied to a single field
+#[derive(Debug, Clone)]
+pub struct FieldFilter {
+    /// Path of the field in the primary type
+    pub path: String,
+    /// How the field is displayed
+    pub kind: FieldFilterKind,
+    /// Ledger signature of the filter
+    pub signature: Vec<u8>,
+}
+
+/// Kind of field filter
+#[derive(Debug, Clone)]
+pub enum FieldFilterKind {
+    /// Show the field under the given name
+    ShowField { display_name: String },
+    /// Show the field as a date and time
+    Datetime { display_name: String },
+    /// Token address of an amount-join pair
+    AmountJoinToken { token_index: u8 },
+    /// Amount of an amount-join pair, formatted with the token at
+    /// `token_index`
+    AmountJoinValue {
+        display_name: String,
+        token_index: u8,
+    },
+}
+
+impl FieldFilter {
+    fn to_apdu(&self, discarded: bool) -> Result<Eip712Apdu, Eip712Error> {
+        let mut data = vec![];
+        let p2 = match &self.kind {
+            FieldFilterKind::ShowField { display_name } => {
+                push_short(&mut data, display_name.as_bytes(), display_name)?;
+                FilterP2::ShowField
+            }
+            FieldFilterKind::Datetime { display_name } => {
+                push_short(&mut data, display_name.as_bytes(), display_name)?;
+                FilterP2::Datetime
+            }
+            FieldFilterKind::AmountJoinToken { token_index } => {
+                data.push(*token_index);
+                FilterP2::AmountJoinToken
+            }
+            FieldFilterKind::AmountJoinValue {
+                display_name,
+                token_index,
+            } => {
+                push_short(&mut data, display_name.as_bytes(), display_name)?;
+                data.push(*token_index);
+                FilterP2::AmountJoinValue
+            }
+        };
+        push_short(&mut data, &self.signature, &self.path)?;
+        Ok(Eip712Apdu::Filter {
+            discarded,
+            p2: p2 as u8,
+            data,
+        })
+    }
+}
+
+/// P2 of EIP712 FILTERING
+#[repr(u8)]
+enum FilterP2 {
+    Activation = 0x00,
+    DiscardedPath = 0x01,
+    MessageInfo = 0x0F,
+    Datetime = 0xFC,
+    AmountJoinToken = 0xFD,
+    AmountJoinValue = 0xFE,
+    ShowField = 0xFF,
+}
+
+/// Payloads of the EIP712 STRUCT APDUs, in the order they are sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Eip712Apdu {
+    /// Struct definition name
+    StructDefName(String),
+    /// Struct definition field (type description and key name)
+    StructDefField(Vec<u8>),
+    /// Name of the root struct being implemented
+    StructImplRoot(String),
+    /// Size of the array being implemented
+    StructImplArray(u8),
+    /// Value of a primitive field
+    StructImplField(Vec<u8>),
+    /// EIP712 FILTERING payload
+    Filter {
+        discarded: bool,
+        p2: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl TypedData {
+    /// Struct definitions of every type in `types`
+    pub(crate) fn struct_definitions(&self) -> Result<Vec<Eip712Apdu>, Eip712Error> {
+        let mut apdus = vec![];
+        for (name, fields) in &self.types {
+            short_len(name.len(), name)?;
+            apdus.push(Eip712Apdu::StructDefName(name.clone()));
+            for field in fields {
+                let field_type: FieldType = field.r#type.parse()?;
+                if let BaseType::Custom(name) = &field_type.base {
+                    if !self.types.contains_key(name) {
+                        return Err(Eip712Error::UnknownType(name.clone()));
+                    }
+                }
+                let mut m = field_type.serialize()?;
+                push_short(&mut m, field.name.as_bytes(), &field.name)?;
+                apdus.push(Eip712Apdu::StructDefField(m));
+            }
+        }
+        Ok(apdus)
+    }
+
+    /// Struct implementations of the domain, then of the message, with the
+    /// filters sent right before the fields they apply to
+    pub(crate) fn struct_implementations(
+        &self,
+        filters: Option<&Eip712Filters>,
+    ) -> Result<Vec<Eip712Apdu>, Eip712Error> {
+        if let Some(filters) = filters {
+            self.validate_filters(filters)?;
+        }
+
+        let mut apdus = vec![];
+        if filters.is_some() {
+            apdus.push(Eip712Apdu::Filter {
+                discarded: false,
+                p2: FilterP2::Activation as u8,
+                data: vec![],
+            });
+        }
+
+        apdus.push(Eip712Apdu::StructImplRoot(DOMAIN_TYPE.into()));
+        self.implement_struct(DOMAIN_TYPE, &self.domain, "", None, &mut apdus)?;
+
+        if let Some(filters) = filters {
+            let info = &filters.message_info;
+            let mut data = vec![];
+            push_short(&mut data, info.display_name.as_bytes(), &info.display_name)?;
+            data.push(short_len(filters.fields.len(), "filters")?);
+            push_short(&mut data, &info.signature, "message info signature")?;
+            apdus.push(Eip712Apdu::Filter {
+                discarded: false,
+                p2: FilterP2::MessageInfo as u8,
+                data,
+            });
+        }
+
+        apdus.push(Eip712Apdu::StructImplRoot(self.primary_type.clone()));
+        self.implement_struct(&self.primary_type, &self.message, "", filters, &mut apdus)?;
+        Ok(apdus)
+    }
+
+    /// Check that every filter targets a primitive field of the primary type
+    pub fn validate_filters(&self, filters: &Eip712Filters) -> Result<(), Eip712Error> {
+        let mut paths = BTreeSet::new();
+        self.field_paths(&self.primary_type, "", &mut paths, 0)?;
+
+        let mut seen = BTreeSet::new();
+        for filter in &filters.fields {
+            if !paths.contains(&filter.path) || !seen.insert(&filter.path) {
+                return Err(Eip712Error::InvalidFilterPath(filter.path.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Collect the paths of every primitive field reachable from `type_name`
+    fn field_paths(
+        &self,
+        type_name: &str,
+        path: &str,
+        paths: &mut BTreeSet<String>,
+        depth: usize,
+    ) -> Result<(), Eip712Error> {
+        // recursive types can only be implemented with finite values, but the
+        // filter paths they allow are unbounded
+        if depth > self.types.len() {
+            return Ok(());
+        }
+        let fields = self
+            .types
+            .get(type_name)
+            .ok_or_else(|| Eip712Error::UnknownType(type_name.to_string()))?;
+        for field in fields {
+            let field_type: FieldType = field.r#type.parse()?;
+            let mut field_path = if path.is_empty() {
+                field.name.clone()
+            } else {
+                format!("{path}.{}", field.name)
+            };
+            for _ in &field_type.array_levels {
+                field_path.push_str(".[]");
+            }
+            match &field_type.base {
+                BaseType::Custom(name) => self.field_paths(name, &field_path, paths, depth + 1)?,
+                _ => {
+                    paths.insert(field_path);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn implement_struct(
+        &self,
+        type_name: &str,
+        value: &Value,
+        path: &str,
+        filters: Option<&Eip712Filters>,
+        apdus: &mut Vec<Eip712Apdu>,
+    ) -> Result<(), Eip712Error> {
+        let fields = self
+            .types
+            .get(type_name)
+            .ok_or_else(|| Eip712Error::UnknownType(type_name.to_string()))?;
+        let values = value.as_object().ok_or_else(|| Eip712Error::InvalidValue {
+            path: path.to_string(),
+            reason: format!("expected a {type_name} object"),
+        })?;
+
+        for field in fields {
+            let field_path = if path.is_empty() {
+                field.name.clone()
+            } else {
+                format!("{path}.{}", field.name)
+            };
+            let field_type: FieldType = field.r#type.parse()?;
+            let value = values
+                .get(&field.name)
+                .ok_or_else(|| Eip712Error::InvalidValue {
+                    path: field_path.clone(),
+                    reason: "missing value".into(),
+                })?;
+            self.implement_value(
+                &field_type.base,
+                &field_type.array_levels,
+                value,
+                &field_path,
+                filters,
+                apdus,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn implement_value(
+        &self,
+        base: &BaseType,
+        array_levels: &[ArrayLevel],
+        value: &Value,
+        path: &str,
+        filters: Option<&Eip712Filters>,
+        apdus: &mut Vec<Eip712Apdu>,
+    ) -> Result<(), Eip712Error> {
+        // the outermost array is the last level of the type name
+        if let Some((outer, inner)) = array_levels.split_last() {
+            let entries = value.as_array().ok_or_else(|| Eip712Error::InvalidValue {
+                path: path.to_string(),
+                reason: "expected an array".into(),
+            })?;
+            if let ArrayLevel::Fixed(size) = outer {
+                if entries.len() != *size as usize {
+                    return Err(Eip712Error::InvalidValue {
+                        path: path.to_string(),
+                        reason: format!("expected {size} entries, got {}", entries.len()),
+                    });
+                }
+            }
+            apdus.push(Eip712Apdu::StructImplArray(short_len(entries.len(), path)?));
+            let entry_path = format!("{path}.[]");
+
+            // filters under an empty array are never reached, so the device
+            // has to be told they are discarded to keep its filter count right
+            if entries.is_empty() {
+                let prefix = format!("{entry_path}.");
+                let discarded = filters
+                    .into_iter()
+                    .flat_map(|f| &f.fields)
+                    .filter(|f| f.path == entry_path || f.path.starts_with(&prefix));
+                for filter in discarded {
+                    let mut data = vec![];
+                    push_short(&mut data, filter.path.as_bytes(), &filter.path)?;
+                    apdus.push(Eip712Apdu::Filter {
+                        discarded: false,
+                        p2: FilterP2::DiscardedPath as u8,
+                        data,
+                    });
+                    apdus.push(filter.to_apdu(true)?);
+                }
+            }
+
+            for entry in entries {
+                self.implement_value(base, inner, entry, &entry_path, filters, apdus)?;
+            }
+            return Ok(());
+        }
+
+        match base {
+            BaseType::Custom(name) => self.implement_struct(name, value, path, filters, apdus),
+            _ => {
+                let value =
+                    encode_primitive(base, value).ok_or_else(|| Eip712Error::InvalidValue {
+                        path: path.to_string(),
+                        reason: format!("{value} does not match {base:?}"),
+                    })?;
+                if value.len() > u16::MAX as usize {
+                    return Err(Eip712Error::TooLong(path.to_string()));
+                }
+                let filter = filters
+                    .into_iter()
+                    .flat_map(|f| &f.fields)
+                    .find(|f| f.path == path);
+                if let Some(filter) = filter {
+                    apdus.push(filter.to_apdu(false)?);
+                }
+                apdus.push(Eip712Apdu::StructImplField(value));
+                Ok(())
+            }
+        }
+    }
+}
+
+// https://eips.ethereum.org/EIPS/eip-712#definition-of-encodetype
+impl TypedData {
+    /// Hash signed by [`crate::EthApp::sign_eip712`]:
+    /// `keccak256("\x19\x01" + domainSeparator + hashStruct(message))`
+    pub fn signing_hash(&self) -> Result<[u8; 32], Eip712Error> {
+        let message_hash = self.hash_struct(&self.primary_type, &self.message)?;
+        Ok(eip712_hash(&self.domain_separator()?, &message_hash))
+    }
+
+    /// `hashStruct(domain)`
+    pub fn domain_separator(&self) -> Result<[u8; 32], Eip712Error> {
+        self.hash_struct(DOMAIN_TYPE, &self.domain)
+    }
+
+    /// `keccak256(typeHash + encodeData(value))`
+    pub fn hash_struct(&self, type_name: &str, value: &Value) -> Result<[u8; 32], Eip712Error> {
+        let mut data = keccak256(self.encode_type(type_name)?.as_bytes()).to_vec();
+        data.extend_from_slice(&self.encode_data(type_name, value, type_name)?);
+        Ok(keccak256(&data))
+    }
+
+    /// `encodeType`: the struct signature, followed by the signatures of the
+    /// structs it references sorted by name, e.g.
+    /// `Mail(Person from,Person to,string contents)Person(string name,address wallet)`
+    pub fn encode_type(&self, type_name: &str) -> Result<String, Eip712Error> {
+        let mut dependencies = BTreeSet::new();
+        self.collect_dependencies(type_name, &mut dependencies)?;
+        dependencies.remove(type_name);
+
+        let mut encoded = String::new();
+        for name in std::iter::once(type_name).chain(dependencies.iter().map(String::as_str)) {
+            let fields = self
+                .types
+                .get(name)
+                .ok_or_else(|| Eip712Error::UnknownType(name.to_string()))?;
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|field| format!("{} {}", field.r#type, field.name))
+                .collect();
+            encoded.push_str(&format!("{name}({})", fields.join(",")));
+        }
+        Ok(encoded)
+    }
+
+    fn collect_dependencies(
+        &self,
+        type_name: &str,
+        dependencies: &mut BTreeSet<String>,
+    ) -> Result<(), Eip712Error> {
+        if !dependencies.insert(type_name.to_string()) {
+            return Ok(());
+        }
+        let fields = self
+            .types
+            .get(type_name)
+            .ok_or_else(|| Eip712Error::UnknownType(type_name.to_string()))?;
+        for field in fields {
+            if let BaseType::Custom(name) = field.r#type.parse::<FieldType>()?.base {
+                self.collect_dependencies(&name, dependencies)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// `encodeData`: every field value encoded on 32 bytes
+    fn encode_data(
+        &self,
+        type_name: &str,
+        value: &Value,
+        path: &str,
+    ) -> Result<Vec<u8>, Eip712Error> {
+        let fields = self
+            .types
+            .get(type_name)
+            .ok_or_else(|| Eip712Error::UnknownType(type_name.to_string()))?;
+        let values = value.as_object().ok_or_else(|| Eip712Error::InvalidValue {
+            path: path.to_string(),
+            reason: format!("expected a {type_name} object"),
+        })?;
+
+        let mut data = Vec::with_capacity(32 * fields.len());
+        for field in fields {
+            let field_path = format!("{path}.{}", field.name);
+            let field_type: FieldType = field.r#type.parse()?;
+            let value = values
+                .get(&field.name)
+                .ok_or_else(|| Eip712Error::InvalidValue {
+                    path: field_path.clone(),
+                    reason: "missing value".into(),
+                })?;
+            data.extend_from_slice(&self.encode_value(
+                &field_type.base,
+                &field_type.array_levels,
+                value,
+                &field_path,
+            )?);
+        }
+        Ok(data)
+    }
+
+    fn encode_value(
+        &self,
+        base: &BaseType,
+        array_levels: &[ArrayLevel],
+        value: &Value,
+        path: &str,
+    ) -> Result<[u8; 32], Eip712Error> {
+        let invalid = |reason: String| Eip712Error::InvalidValue {
+            path: path.to_string(),
+            reason,
+        };
+
+        // arrays are the hash of their encoded entries
+        if let Some((_, inner)) = array_levels.split_last() {
+            let entries = value
+                .as_array()
+                .ok_or_else(|| invalid("expected an array".into()))?;
+            let mut data = Vec::with_capacity(32 * entries.len());
+            for entry in entries {
+                data.extend_from_slice(&self.encode_value(base, inner, entry, path)?);
+            }
+            return Ok(keccak256(&data));
+        }
+
+        if let BaseType::Custom(name) = base {
+            return self.hash_struct(name, value);
+        }
+        let encoded = encode_primitive(base, value)
+            .ok_or_else(|| invalid(format!("{value} does not match {base:?}")))?;
+        let mut word = [0u8; 32];
+        match base {
+            BaseType::String | BaseType::DynamicBytes => return Ok(keccak256(&encoded)),
+            // left aligned
+            BaseType::FixedBytes(_) => word[..encoded.len()].copy_from_slice(&encoded),
+            // sign extended
+            BaseType::Int(_) if encoded[0] & 0x80 != 0 => {
+                word = [0xff; 32];
+                word[32 - encoded.len()..].copy_from_slice(&encoded);
+            }
+            _ => word[32 - encoded.len()..].copy_from_slice(&encoded),
+        }
+        Ok(word)
+    }
+}
+
+fn short_len(len: usize, what: &str) -> Result<u8, Eip712Error> {
+    u8::try_from(len).map_err(|_| Eip712Error::TooLong(what.to_string()))
+}
+
+/// Push a length-prefixed byte string
+fn push_short(m: &mut Vec<u8>, bytes: &[u8], what: &str) -> Result<(), Eip712Error> {
+    m.push(short_len(bytes.len(), what)?);
+    m.extend_from_slice(bytes);
+    Ok(())
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    hex::decode(s.strip_prefix("0x").unwrap_or(s)).ok()
+}
+
+/// Encode a primitive value the way the device expects it
+fn encode_primitive(base: &BaseType, value: &Value) -> Option<Vec<u8>> {
+    match base {
+        BaseType::Int(size) => encode_integer(value, *size, true),
+        BaseType::Uint(size) => encode_integer(value, *size, false),
+        BaseType::Address => value
+            .as_str()
+            .and_then(decode_hex)
+            .filter(|address| address.len() == 20),
+        BaseType::Bool => value.as_bool().map(|b| vec![b as u8]),
+        BaseType::String => value.as_str().map(|s| s.as_bytes().to_vec()),
+        BaseType::FixedBytes(size) => value
+            .as_str()
+            .and_then(decode_hex)
+            .filter(|bytes| bytes.len() <= *size as usize),
+        BaseType::DynamicBytes => value.as_str().and_then(decode_hex),
+        BaseType::Custom(_) => None,
+    }
+}
+
+/// Encode an integer given as a JSON number, a decimal string or a hex string
+/// into `size` big-endian bytes (two's complement if negative)
+fn encode_integer(value: &Value, size: u8, signed: bool) -> Option<Vec<u8>> {
+    let (negative, magnitude) = match value {
+        Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(n), _) => (false, n.to_string()),
+            (None, Some(n)) => (true, n.unsigned_abs().to_string()),
+            _ => return None,
+        },
+        Value::String(s) => match s.strip_prefix('-') {
+            Some(s) => (true, s.to_string()),
+            None => (false, s.clone()),
+        },
+        _ => return None,
+    };
+
+    let mut word = match magnitude.strip_prefix("0x") {
+        Some(hex) => parse_hex_word(hex)?,
+        None => parse_decimal_word(&magnitude)?,
+    };
+    let negative = negative && word.iter().any(|b| *b != 0);
+    if negative {
+        if !signed {
+            return None;
+        }
+        word = twos_complement(word);
+    }
+
+    let (high, low) = word.split_at(32 - size as usize);
+    let fill = if negative { 0xff } else { 0x00 };
+    if high.iter().any(|b| *b != fill) {
+        return None;
+    }
+    if signed && (low[0] & 0x80 != 0) != negative {
+        return None;
+    }
+    Some(low.to_vec())
+}
+
+fn parse_hex_word(hex: &str) -> Option<[u8; 32]> {
+    if hex.is_empty() || hex.len() > 64 {
+        return None;
+    }
+    let padded = format!("{hex:0>64}");
+    hex::decode(padded).ok()?.try_into().ok()
+}
+
+fn parse_decimal_word(decimal: &str) -> Option<[u8; 32]> {
+    if decimal.is_empty() {
+        return None;
+    }
+    let mut word = [0u8; 32];
+    for digit in decimal.chars() {
+        let mut carry = digit.to_digit(10)?;
+        for byte in word.iter_mut().rev() {
+            let v = *byte as u32 * 10 + carry;
+            *byte = v as u8;
+            carry = v >> 8;
+        }
+        if carry != 0 {
+            return None;
+        }
+    }
+    Some(word)
+}
+
+fn twos_complement(mut word: [u8; 32]) -> [u8; 32] {
+    let mut carry = true;
+    for byte in word.iter_mut().rev() {
+        let (v, overflow) = (!*byte).overflowing_add(carry as u8);
+        *byte = v;
+        carry = overflow;
+    }
+    word
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> FieldType {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_field_types() {
+        assert_eq!(BaseType::Uint(32), parse("uint").base);
+        assert_eq!(BaseType::Int(32), parse("int").base);
+        assert_eq!(BaseType::Int(1), parse("int8").base);
+        assert_eq!(BaseType::Uint(20), parse("uint160").base);
+        assert_eq!(BaseType::FixedBytes(32), parse("bytes32").base);
+        assert_eq!(BaseType::DynamicBytes, parse("bytes").base);
+        assert_eq!(BaseType::Custom("Person".into()), parse("Person").base);
+        assert_eq!(
+            vec![ArrayLevel::Fixed(2), ArrayLevel::Dynamic],
+            parse("Person[2][]").array_levels
+        );
+
+        for invalid in [
+            "", "uint7", "uint264", "int0", "bytes0", "bytes33", "uint[", "uint[x]",
+        ] {
+            assert!(invalid.parse::<FieldType>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn serializes_field_types() {
+        assert_eq!(
+            vec![0xc2, 0x20, 0x02, 0x01, 0x02, 0x00],
+            parse("uint256[2][]").serialize().unwrap()
+        );
+        assert_eq!(vec![0x03], parse("address").serialize().unwrap());
+        assert_eq!(vec![0x46, 0x20], parse("bytes32").serialize().unwrap());
+        assert_eq!(
+            vec![0x80, 0x06, b'P', b'e', b'r', b's', b'o', b'n', 0x01, 0x00],
+            parse("Person[]").serialize().unwrap()
+        );
+    }
+
+    #[test]
+    fn encodes_integers() {
+        let int8 = |v: Value| encode_integer(&v, 1, true);
+        assert_eq!(Some(vec![0xff]), int8((-1).into()));
+        assert_eq!(Some(vec![0x80]), int8("-128".into()));
+        assert_eq!(Some(vec![0x7f]), int8("0x7f".into()));
+        assert_eq!(Some(vec![0x00]), int8("-0".into()));
+        assert_eq!(None, int8("-129".into()));
+        assert_eq!(None, int8(128.into()));
+
+        assert_eq!(
+            Some(vec![0xff, 0xfe]),
+            encode_integer(&(-2).into(), 2, true)
+        );
+        assert_eq!(Some(vec![0xff]), encode_integer(&255.into(), 1, false));
+        assert_eq!(None, encode_integer(&256.into(), 1, false));
+        assert_eq!(None, encode_integer(&(-1).into(), 32, false));
+
+        let max = "115792089237316195423570985008687907853269984665640564039457584007913129639935";
+        assert_eq!(Some(vec![0xff; 32]), encode_integer(&max.into(), 32, false));
+        assert_eq!(None, encode_integer(&format!("{max}0").into(), 32, false));
+    }
+
+    const FILTERED_TYPED_DATA: &str = r#"{
+        "types": {
+            "EIP712Domain": [{ "name": "name", "type": "string" }],
+            "Person": [
+                { "name": "name", "type": "string" },
+                { "name": "wallets", "type": "address[]" }
+            ],
+            "Mail": [
+                { "name": "from", "type": "Person" },
+                { "name": "to", "type": "Person[]" },
+                { "name": "contents", "type": "string" }
+            ]
+        },
+        "primaryType": "Mail",
+        "domain": { "name": "Ether Mail" },
+        "message": {
+            "from": { "name": "Cow", "wallets": ["0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"] },
+            "to": [],
+            "contents": "Hello, Bob!"
+        }
+    }"#;
+
+    fn filters(paths: &[&str]) -> Eip712Filters {
+        Eip712Filters {
+            message_info: MessageInfoFilter {
+                display_name: "Mail".into(),
+                signature: vec![0xaa; 2],
+            },
+            fields: paths
+                .iter()
+                .map(|path| FieldFilter {
+                    path: path.to_string(),
+                    kind: FieldFilterKind::ShowField {
+                        display_name: "Field".into(),
+                    },
+                    signature: vec![0xbb; 2],
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_filter_paths() {
+        let typed_data: TypedData = FILTERED_TYPED_DATA.parse().unwrap();
+        typed_data
+            .validate_filters(&filters(&["from.name", "from.wallets.[]", "to.[].name"]))
+            .unwrap();
+
+        for path in [
+            // unknown field
+            "subject",
+            // array fields are reached through `.[]`
+            "to.name",
+            "from.wallets",
+            // past a primitive field
+            "from.name.first",
+            "from.wallets.[].[]",
+            // structs are not displayed themselves
+            "from",
+        ] {
+            let error = typed_data.validate_filters(&filters(&[path])).unwrap_err();
+            assert!(
+                matches!(&error, Eip712Error::InvalidFilterPath(p) if p == path),
+                "{path}: {error}"
+            );
+        }
+
+        let error = typed_data
+            .validate_filters(&filters(&["contents", "contents"]))
+            .unwrap_err();
+        assert!(matches!(error, Eip712Error::InvalidFilterPath(p) if p == "contents"));
+    }
+
+    #[test]
+    fn sends_filters_before_their_fields() {
+        let typed_data: TypedData = FILTERED_TYPED_DATA.parse().unwrap();
+        let filters = filters(&["from.name", "to.[].name", "contents"]);
+        let apdus = typed_data.struct_implementations(Some(&filters)).unwrap();
+
+        let sequence: Vec<String> = apdus
+            .iter()
+            .map(|apdu| match apdu {
+                Eip712Apdu::Filter {
+                    discarded,
+                    p2,
+                    data,
+                } => format!("filter {p2:#04x} {discarded} {}", hex::encode(data)),
+                Eip712Apdu::StructImplRoot(name) => format!("root {name}"),
+                Eip712Apdu::StructImplArray(size) => format!("array {size}"),
+                Eip712Apdu::StructImplField(value) => format!("field {}", hex::encode(value)),
+                other => panic!("unexpected {other:?}"),
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                "filter 0x00 false ".to_string(),
+                "root EIP712Domain".into(),
+                format!("field {}", hex::encode("Ether Mail")),
+                // display name, filter count, signature
+                "filter 0x0f false 044d61696c0302aaaa".into(),
+                "root Mail".into(),
+                "filter 0xff false 054669656c6402bbbb".into(),
+                format!("field {}", hex::encode("Cow")),
+                "array 1".into(),
+                "field cd2a3d9f938e13cd947ec05abc7fe734df8dd826".into(),
+                "array 0".into(),
+                format!("filter 0x01 false 0a{}", hex::encode("to.[].name")),
+                "filter 0xff true 054669656c6402bbbb".into(),
+                "filter 0xff false 054669656c6402bbbb".into(),
+                format!("field {}", hex::encode("Hello, Bob!")),
+            ],
+            sequence
+        );
+    }
+}
@@ -0,0 +1,10 @@
+use tiny_keccak::{Hasher, Keccak};
+
+/// Keccak-256 digest of `bytes`
+pub(crate) fn keccak256(bytes: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak::v256();
+    hasher.update(bytes);
+    let mut digest = [0u8; 32];
+    hasher.finalize(&mut digest);
+    digest
+}
@@ -1,19 +1,44 @@
+pub(crate) mod address;
+pub mod bip32;
 pub(crate) mod command;
+pub mod eip712;
+pub(crate) mod hash;
+pub mod recovery;
+pub mod resolver;
+pub(crate) mod rlp;
+pub(crate) mod signature;
+pub mod transaction;
 pub(crate) mod types;
+pub mod verification;
 
+pub use address::*;
 pub use command::get_address::*;
 pub use command::get_app_configuration::*;
+pub use command::get_eth2_public_key::*;
 pub use command::provide_erc20_token_info::*;
+pub use command::provide_nft_info::*;
+pub use command::set_plugin::*;
 pub use command::sign_transaction::*;
 use ledger_transport::{APDUCommand, APDUErrorCode, Exchange};
 use ledger_zondax_generic::{App, LedgerAppError};
+pub use rlp::RlpError;
+pub use signature::*;
 pub use types::*;
+use verification::{CalEnvironment, CalKeys};
+
+/// Default size of the data of a chunked command
+pub(crate) const CHUNK_SIZE: usize = 250;
+/// Maximum size of the data of an APDU
+pub(crate) const MAX_CHUNK_SIZE: usize = 255;
 
 // https://github.com/LedgerHQ/app-ethereum/blob/develop/doc/ethapp.adoc#general-purpose-apdus
 // https://github.com/LedgerHQ/ledger-live/blob/develop/libs/ledgerjs/packages/hw-app-eth/src/Eth.ts
 #[derive(Debug)]
 pub struct EthApp<E: Exchange> {
     transport: E,
+    cal_keys: Option<CalKeys>,
+    #[cfg(feature = "recovery")]
+    verify_signatures: bool,
 }
 
 impl<E: Exchange> App for EthApp<E> {
@@ -23,7 +48,34 @@ impl<E: Exchange> App for EthApp<E> {
 impl<E: Exchange> EthApp<E> {
     /// Create a new [`EthApp`] with the given transport
     pub const fn new(transport: E) -> Self {
-        EthApp { transport }
+        EthApp {
+            transport,
+            cal_keys: None,
+            #[cfg(feature = "recovery")]
+            verify_signatures: false,
+        }
+    }
+
+    /// Verify signed descriptors against the CAL keys of `environment` before
+    /// sending them to the device, see [`CalKeys::new`]
+    pub fn with_cal_verification(mut self, environment: CalEnvironment) -> Self {
+        self.cal_keys = Some(CalKeys::new(environment));
+        self
+    }
+
+    /// Verify every signed descriptor against custom CAL keys before sending
+    /// it to the device
+    pub fn with_cal_keys(mut self, cal_keys: CalKeys) -> Self {
+        self.cal_keys = Some(cal_keys);
+        self
+    }
+
+    /// Check every returned signature against the address of its derivation
+    /// path, to detect a wrong account or a tampered response
+    #[cfg(feature = "recovery")]
+    pub fn with_signature_verification(mut self) -> Self {
+        self.verify_signatures = true;
+        self
     }
 }
 
@@ -32,15 +84,56 @@ where
     E: Exchange + Send + Sync,
     E::Error: std::error::Error,
 {
+    /// Exchange a single command, turning any non-success status word into an
+    /// error
+    pub(crate) async fn exchange_checked(
+        &self,
+        command: &APDUCommand<Vec<u8>>,
+    ) -> Result<ledger_transport::APDUAnswer<E::AnswerType>, EthError<E::Error>> {
+        let response = self
+            .transport
+            .exchange(command)
+            .await
+            .map_err(LedgerAppError::TransportError)?;
+        match response.error_code() {
+            Ok(APDUErrorCode::NoError) => {}
+            Ok(err) => {
+                return Err(EthError::Ledger(LedgerAppError::AppSpecific(
+                    err as _,
+                    err.description(),
+                )))
+            }
+            Err(err) => {
+                return Err(EthError::Ledger(LedgerAppError::AppSpecific(
+                    err,
+                    "[APDU_ERROR] Unknown".to_string(),
+                )))
+            }
+        }
+        Ok(response)
+    }
+
     pub async fn send_chunks(
         &self,
-        mut command: APDUCommand<Vec<u8>>,
+        command: APDUCommand<Vec<u8>>,
     ) -> Result<ledger_transport::APDUAnswer<E::AnswerType>, LedgerAppError<E::Error>> {
         let chunks = command
             .data
-            .chunks(250)
+            .chunks(CHUNK_SIZE)
             .map(|c| c.to_vec())
             .collect::<Vec<Vec<u8>>>();
+        self.send_chunk_list(command, chunks).await
+    }
+
+    /// Send `chunks` as the data of `command`, split by the caller
+    pub(crate) async fn send_chunk_list(
+        &self,
+        mut command: APDUCommand<Vec<u8>>,
+        chunks: Vec<Vec<u8>>,
+    ) -> Result<ledger_transport::APDUAnswer<E::AnswerType>, LedgerAppError<E::Error>> {
+        if chunks.iter().any(|c| c.len() > MAX_CHUNK_SIZE) {
+            return Err(LedgerAppError::InvalidMessageSize);
+        }
         match chunks.len() {
             0 => return Err(LedgerAppError::InvalidEmptyMessage),
             n if n > 255 => return Err(LedgerAppError::InvalidMessageSize),
@@ -64,7 +157,6 @@ where
         // Send message chunks
         let p1 = ChunkPayloadType::Subsequent as u8;
         for chunk in rest {
-            dbg!(&chunk);
             let command = APDUCommand {
                 cla: command.cla,
                 ins: command.ins,
@@ -0,0 +1,139 @@
+//! Hashes signed by the device and, with the `recovery` feature, local
+//! verification of the returned signatures
+// https://eips.ethereum.org/EIPS/eip-191
+// https://eips.ethereum.org/EIPS/eip-712
+
+use crate::hash::keccak256;
+
+/// Hash signed by [`crate::EthApp::sign_personal_message`]:
+/// `keccak256("\x19Ethereum Signed Message:\n" + len(message) + message)`
+pub fn eip191_hash(message: &[u8]) -> [u8; 32] {
+    let mut data = format!("\x19Ethereum Signed Message:\n{}", message.len()).into_bytes();
+    data.extend_from_slice(message);
+    keccak256(&data)
+}
+
+/// Hash signed by [`crate::EthApp::sign_eip712_hashed`]:
+/// `keccak256("\x19\x01" + domainSeparator + hashStruct(message))`
+pub fn eip712_hash(domain_separator: &[u8; 32], message_hash: &[u8; 32]) -> [u8; 32] {
+    let mut data = vec![0x19, 0x01];
+    data.extend_from_slice(domain_separator);
+    data.extend_from_slice(message_hash);
+    keccak256(&data)
+}
+
+#[cfg(feature = "recovery")]
+pub use self::secp::RecoveryError;
+
+#[cfg(feature = "recovery")]
+mod secp {
+    use ledger_transport::Exchange;
+    use secp256k1::ecdsa::{self, RecoverableSignature, RecoveryId};
+    use secp256k1::{Message, PublicKey, SECP256K1};
+
+    use crate::types::{DerivationPath, EthError};
+    use crate::{EthAddress, EthApp, Signature};
+
+    /// Recovery Error
+    #[derive(Debug, thiserror::Error)]
+    pub enum RecoveryError {
+        /// `v` is none of `yParity`, 27/28 or an EIP-155 `v`
+        #[error("invalid signature v {0}")]
+        InvalidV(u64),
+
+        /// Malformed signature or public key, or failed verification
+        #[error("secp256k1 | {0}")]
+        Secp256k1(#[from] secp256k1::Error),
+
+        /// The signature was not made by the account it was requested from
+        #[error("signed by {recovered} instead of {expected}")]
+        AddressMismatch {
+            expected: EthAddress,
+            recovered: EthAddress,
+        },
+    }
+
+    impl Signature {
+        fn recovery_id(&self) -> Result<RecoveryId, RecoveryError> {
+            let parity = self
+                .y_parity()
+                .map_err(|_| RecoveryError::InvalidV(self.v))?;
+            // safe, parity is 0 or 1
+            Ok(RecoveryId::from_i32(parity.into()).unwrap())
+        }
+
+        /// Recover the public key that signed `hash`
+        pub fn recover_public_key(&self, hash: &[u8; 32]) -> Result<PublicKey, RecoveryError> {
+            let mut compact = [0u8; 64];
+            compact[..32].copy_from_slice(&self.r);
+            compact[32..].copy_from_slice(&self.s);
+            let signature = RecoverableSignature::from_compact(&compact, self.recovery_id()?)?;
+            let message = Message::from_slice(hash)?;
+            Ok(SECP256K1.recover_ecdsa(&message, &signature)?)
+        }
+
+        /// Recover the address that signed `hash`
+        pub fn recover_address(&self, hash: &[u8; 32]) -> Result<EthAddress, RecoveryError> {
+            let public_key = self.recover_public_key(hash)?;
+            // safe, a recovered key is valid
+            Ok(EthAddress::from_public_key(&public_key.serialize()).unwrap())
+        }
+
+        /// Verify the signature of `hash` against a SEC1 encoded public key
+        pub fn verify(&self, public_key: &[u8], hash: &[u8; 32]) -> Result<(), RecoveryError> {
+            let public_key = PublicKey::from_slice(public_key)?;
+            let mut compact = [0u8; 64];
+            compact[..32].copy_from_slice(&self.r);
+            compact[32..].copy_from_slice(&self.s);
+            let signature = ecdsa::Signature::from_compact(&compact)?;
+            let message = Message::from_slice(hash)?;
+            Ok(SECP256K1.verify_ecdsa(&message, &signature, &public_key)?)
+        }
+    }
+
+    impl<E> EthApp<E>
+    where
+        E: Exchange + Send + Sync,
+        E::Error: std::error::Error,
+    {
+        /// Check that `signature` of `hash` was made by the account at `path`,
+        /// when signature verification is enabled
+        pub(crate) async fn check_signature(
+            &self,
+            path: &DerivationPath,
+            hash: [u8; 32],
+            signature: &Signature,
+        ) -> Result<(), EthError<E::Error>> {
+            if !self.verify_signatures {
+                return Ok(());
+            }
+            let expected = self.address(path, None, None).await?.address;
+            let recovered = signature.recover_address(&hash)?;
+            if expected != recovered {
+                return Err(RecoveryError::AddressMismatch {
+                    expected,
+                    recovered,
+                }
+                .into());
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(feature = "recovery"))]
+impl<E> crate::EthApp<E>
+where
+    E: ledger_transport::Exchange + Send + Sync,
+    E::Error: std::error::Error,
+{
+    /// Signature verification needs the `recovery` feature
+    pub(crate) async fn check_signature(
+        &self,
+        _path: &crate::DerivationPath,
+        _hash: [u8; 32],
+        _signature: &crate::Signature,
+    ) -> Result<(), crate::EthError<E::Error>> {
+        Ok(())
+    }
+}
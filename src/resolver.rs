@@ -0,0 +1,296 @@
+//! Offline transaction resolution: find the signed descriptors a transaction
+//! needs to be clear-signed by decoding its calldata and looking them up in a
+//! local asset database
+//!
+//! The database can be filled from Ledger's crypto asset list (CAL):
+//! - ERC 20 tokens from the `erc20-signatures` file, see
+//!   [`AssetDatabase::load_erc20_signatures`]
+//! - external plugins from the `plugins/<network>.json` files, see
+//!   [`AssetDatabase::load_plugins`]
+//! - NFT collections and internal plugin bindings from the payloads served by
+//!   Ledger's NFT metadata service, see [`AssetDatabase::add_nft`] and
+//!   [`AssetDatabase::add_plugin`]
+//!
+//! It can also be stored as a JSON document of this crate's own schema, where
+//! every descriptor is serialized, in hex format, as it is sent to the device:
+//!
+//! ```json
+//! {
+//!   "tokens": [{ "chain_id": 1, "contract_address": "0x…", "payload": "…" }],
+//!   "nfts": [{ "chain_id": 1, "contract_address": "0x…", "payload": "…" }],
+//!   "plugins": [
+//!     { "chain_id": 1, "contract_address": "0x…", "selector": "0x42842e0e", "payload": "…" }
+//!   ],
+//!   "external_plugins": [
+//!     {
+//!       "chain_id": 1,
+//!       "contract_address": "0x…",
+//!       "selector": "0x…",
+//!       "payload": "…",
+//!       "signature": "…"
+//!     }
+//!   ]
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::transaction::{self, TransactionError};
+use crate::types::{
+    DescriptorError, DescriptorReader, ExternalPluginData, LedgerEthTransactionResolution,
+};
+use crate::{Erc20TokenInfo, NftInfo, PluginInfo};
+
+/// `transfer(address,uint256)`
+pub const TRANSFER: [u8; 4] = [0xa9, 0x05, 0x9c, 0xbb];
+/// `approve(address,uint256)`, shared by ERC 20 and ERC 721
+pub const APPROVE: [u8; 4] = [0x09, 0x5e, 0xa7, 0xb3];
+/// `transferFrom(address,address,uint256)`, shared by ERC 20 and ERC 721
+pub const TRANSFER_FROM: [u8; 4] = [0x23, 0xb8, 0x72, 0xdd];
+/// ERC 721 `safeTransferFrom(address,address,uint256)`
+pub const SAFE_TRANSFER_FROM: [u8; 4] = [0x42, 0x84, 0x2e, 0x0e];
+/// ERC 721 `safeTransferFrom(address,address,uint256,bytes)`
+pub const SAFE_TRANSFER_FROM_WITH_DATA: [u8; 4] = [0xb8, 0x8d, 0x4f, 0xde];
+/// ERC 1155 `safeTransferFrom(address,address,uint256,uint256,bytes)`
+pub const SAFE_TRANSFER_FROM_1155: [u8; 4] = [0xf2, 0x42, 0x43, 0x2a];
+/// `setApprovalForAll(address,bool)`
+pub const SET_APPROVAL_FOR_ALL: [u8; 4] = [0xa2, 0x2c, 0xb4, 0x65];
+
+/// Resolver Error
+#[derive(Debug, thiserror::Error)]
+pub enum ResolverError {
+    /// Transaction could not be decoded
+    #[error("Transaction | {0}")]
+    Transaction(#[from] TransactionError),
+
+    /// CAL descriptor could not be parsed
+    #[error("Descriptor | {0}")]
+    Descriptor(#[from] DescriptorError),
+
+    /// Asset database could not be parsed
+    #[error("Invalid asset database: {0}")]
+    Json(#[from] serde_json::Error),
+
+    /// Asset database could not be read
+    #[error("Asset database | {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Descriptor of a contract
+#[derive(Debug, Clone, Deserialize)]
+pub struct ContractEntry {
+    pub chain_id: u64,
+    pub contract_address: String,
+    /// Serialized descriptor (hex format)
+    pub payload: String,
+}
+
+/// Descriptor of a contract method
+#[derive(Debug, Clone, Deserialize)]
+pub struct MethodEntry {
+    pub chain_id: u64,
+    pub contract_address: String,
+    pub selector: String,
+    /// Serialized descriptor (hex format)
+    pub payload: String,
+}
+
+/// External plugin descriptor of a contract method
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExternalPluginEntry {
+    pub chain_id: u64,
+    pub contract_address: String,
+    pub selector: String,
+    /// Plugin payload (hex format)
+    pub payload: String,
+    /// Signature of the payload (hex format)
+    pub signature: String,
+}
+
+/// Local asset database of signed descriptors
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AssetDatabase {
+    /// ERC 20 token descriptors
+    #[serde(default)]
+    pub tokens: Vec<ContractEntry>,
+    /// NFT collection descriptors
+    #[serde(default)]
+    pub nfts: Vec<ContractEntry>,
+    /// Internal plugin descriptors
+    #[serde(default)]
+    pub plugins: Vec<MethodEntry>,
+    /// External plugin descriptors
+    #[serde(default)]
+    pub external_plugins: Vec<ExternalPluginEntry>,
+}
+
+/// Method of a contract in Ledger's `plugins/<network>.json` files
+#[derive(Debug, Clone, Deserialize)]
+struct CalPluginMethod {
+    serialized_data: String,
+    signature: String,
+}
+
+impl AssetDatabase {
+    /// Parse an asset database from JSON
+    pub fn from_json(json: &str) -> Result<Self, ResolverError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Load an asset database from a JSON file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ResolverError> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    /// Add the tokens of Ledger's `erc20-signatures` file, once base64
+    /// decoded: serialized descriptors, each prefixed by its length (uint4be)
+    pub fn load_erc20_signatures(&mut self, data: &[u8]) -> Result<(), ResolverError> {
+        let mut data = data;
+        while !data.is_empty() {
+            let mut reader = DescriptorReader::new(data);
+            let len = reader.u32("descriptor length")?;
+            let payload = reader.bytes(len as usize, "descriptor")?;
+            data = reader.rest();
+            let info = Erc20TokenInfo::parse(payload)?;
+            self.tokens.push(ContractEntry {
+                chain_id: info.chain_id.into(),
+                contract_address: hex::encode(info.address),
+                payload: hex::encode(payload),
+            });
+        }
+        Ok(())
+    }
+
+    /// Add the external plugins of one of Ledger's `plugins/<network>.json`
+    /// files. They map contract addresses to method selectors and do not hold
+    /// the chain id, which is the one of the network.
+    pub fn load_plugins(&mut self, chain_id: u64, json: &str) -> Result<(), ResolverError> {
+        let contracts: BTreeMap<String, BTreeMap<String, serde_json::Value>> =
+            serde_json::from_str(json)?;
+        for (contract_address, methods) in contracts {
+            for (selector, method) in methods {
+                // contracts also hold their ABI and the tokens of interest
+                if !is_selector(&selector) {
+                    continue;
+                }
+                let method: CalPluginMethod = serde_json::from_value(method)?;
+                self.external_plugins.push(ExternalPluginEntry {
+                    chain_id,
+                    contract_address: contract_address.clone(),
+                    selector,
+                    payload: method.serialized_data,
+                    signature: method.signature,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Add an NFT collection descriptor, as served by Ledger's NFT metadata
+    /// service (hex format)
+    pub fn add_nft(&mut self, payload: &str) -> Result<(), ResolverError> {
+        let info = NftInfo::parse(&decode_hex(payload)?)?;
+        self.nfts.push(ContractEntry {
+            chain_id: info.chain_id,
+            contract_address: hex::encode(info.contract_address),
+            payload: payload.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Add an internal plugin binding, as served by Ledger's NFT metadata
+    /// service (hex format)
+    pub fn add_plugin(&mut self, payload: &str) -> Result<(), ResolverError> {
+        let info = PluginInfo::parse(&decode_hex(payload)?)?;
+        self.plugins.push(MethodEntry {
+            chain_id: info.chain_id,
+            contract_address: hex::encode(info.contract_address),
+            selector: hex::encode(info.selector),
+            payload: payload.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Build the resolution of an unsigned raw transaction, as passed to
+    /// [`crate::EthApp::sign`]. Transactions that need no descriptor (plain
+    /// transfers, deployments, unknown contracts) get an empty resolution.
+    pub fn resolve(&self, raw_tx: &[u8]) -> Result<LedgerEthTransactionResolution, ResolverError> {
+        let mut resolution = LedgerEthTransactionResolution::default();
+        let tx = transaction::decode(raw_tx)?;
+        let (to, selector) = match (tx.to(), tx.data().get(..4)) {
+            // safe due to get() range
+            (Some(to), Some(selector)) => (to, <[u8; 4]>::try_from(selector).unwrap()),
+            _ => return Ok(resolution),
+        };
+        // legacy transactions without EIP-155 are mainnet ones
+        let chain_id = tx.chain_id().unwrap_or(1);
+
+        if let Some(entry) = self.external_plugins.iter().find(|e| {
+            e.chain_id == chain_id
+                && same_hex(&e.contract_address, &to)
+                && same_hex(&e.selector, &selector)
+        }) {
+            resolution.external_plugins.push(ExternalPluginData {
+                payload: entry.payload.clone(),
+                signature: entry.signature.clone(),
+            });
+        }
+
+        let token = find_contract(&self.tokens, chain_id, &to);
+        let nft = find_contract(&self.nfts, chain_id, &to);
+        let is_nft_call = match selector {
+            TRANSFER => false,
+            // shared selectors: an NFT collection takes precedence over a token
+            APPROVE | TRANSFER_FROM => nft.is_some(),
+            SAFE_TRANSFER_FROM
+            | SAFE_TRANSFER_FROM_WITH_DATA
+            | SAFE_TRANSFER_FROM_1155
+            | SET_APPROVAL_FOR_ALL => true,
+            _ => return Ok(resolution),
+        };
+
+        if is_nft_call {
+            if let Some(nft) = nft {
+                resolution.nfts.push(nft.payload.clone());
+            }
+            if let Some(plugin) = self.plugins.iter().find(|e| {
+                e.chain_id == chain_id
+                    && same_hex(&e.contract_address, &to)
+                    && same_hex(&e.selector, &selector)
+            }) {
+                resolution.plugin.push(plugin.payload.clone());
+            }
+        } else if let Some(token) = token {
+            resolution.erc20_tokens.push(token.payload.clone());
+        }
+
+        Ok(resolution)
+    }
+}
+
+fn find_contract<'a>(
+    entries: &'a [ContractEntry],
+    chain_id: u64,
+    address: &[u8; 20],
+) -> Option<&'a ContractEntry> {
+    entries
+        .iter()
+        .find(|e| e.chain_id == chain_id && same_hex(&e.contract_address, address))
+}
+
+/// Compare a hex string, with or without `0x` and in any case, with bytes
+fn same_hex(hex: &str, bytes: &[u8]) -> bool {
+    matches!(hex::decode(hex.strip_prefix("0x").unwrap_or(hex)), Ok(decoded) if decoded == bytes)
+}
+
+/// Whether a key of a contract in Ledger's plugins files is a selector
+fn is_selector(key: &str) -> bool {
+    matches!(key.strip_prefix("0x").map(hex::decode), Some(Ok(bytes)) if bytes.len() == 4)
+}
+
+fn decode_hex(payload: &str) -> Result<Vec<u8>, DescriptorError> {
+    Ok(hex::decode(payload.strip_prefix("0x").unwrap_or(payload))?)
+}
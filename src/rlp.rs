@@ -0,0 +1,205 @@
+//! Minimal RLP encoding and decoding
+// https://ethereum.org/en/developers/docs/data-structures-and-encoding/rlp/
+
+/// RLP Error
+#[derive(Debug, thiserror::Error)]
+pub enum RlpError {
+    /// Input ended before the end of an item
+    #[error("unexpected end of RLP input")]
+    UnexpectedEnd,
+
+    /// Bytes left after the top level item
+    #[error("{0} trailing bytes after RLP item")]
+    TrailingBytes(usize),
+
+    /// Length prefix that does not fit in memory
+    #[error("RLP length overflow")]
+    LengthOverflow,
+
+    /// An item does not have the expected shape
+    #[error("unexpected RLP item: expected {0}")]
+    Unexpected(&'static str),
+}
+
+/// Decoded RLP item, borrowing from the input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Item<'a> {
+    Bytes(&'a [u8]),
+    List(Vec<Item<'a>>),
+}
+
+impl<'a> Item<'a> {
+    pub(crate) fn as_bytes(&self) -> Result<&'a [u8], RlpError> {
+        match self {
+            Item::Bytes(bytes) => Ok(bytes),
+            Item::List(_) => Err(RlpError::Unexpected("bytes")),
+        }
+    }
+
+    pub(crate) fn as_list(&self) -> Result<&[Item<'a>], RlpError> {
+        match self {
+            Item::List(items) => Ok(items),
+            Item::Bytes(_) => Err(RlpError::Unexpected("list")),
+        }
+    }
+
+    /// Big-endian integer of at most 8 bytes
+    pub(crate) fn as_u64(&self) -> Result<u64, RlpError> {
+        let bytes = self.as_bytes()?;
+        if bytes.len() > 8 {
+            return Err(RlpError::Unexpected("integer of at most 8 bytes"));
+        }
+        Ok(bytes.iter().fold(0, |n, b| n << 8 | *b as u64))
+    }
+
+    /// Big-endian integer of at most 16 bytes
+    pub(crate) fn as_u128(&self) -> Result<u128, RlpError> {
+        let bytes = self.as_bytes()?;
+        if bytes.len() > 16 {
+            return Err(RlpError::Unexpected("integer of at most 16 bytes"));
+        }
+        Ok(bytes.iter().fold(0, |n, b| n << 8 | *b as u128))
+    }
+
+    /// Bytes of exactly `N` bytes, such as a hash
+    pub(crate) fn as_array<const N: usize>(&self) -> Result<[u8; N], RlpError> {
+        self.as_bytes()?
+            .try_into()
+            .map_err(|_| RlpError::Unexpected("fixed size bytes"))
+    }
+
+    /// Empty bytes or a 20 bytes address
+    pub(crate) fn as_address(&self) -> Result<Option<[u8; 20]>, RlpError> {
+        match self.as_bytes()? {
+            [] => Ok(None),
+            bytes => bytes
+                .try_into()
+                .map(Some)
+                .map_err(|_| RlpError::Unexpected("address")),
+        }
+    }
+}
+
+/// Header of an item: whether it is a list, the header length and the payload
+/// length
+pub(crate) fn header(data: &[u8]) -> Result<(bool, usize, usize), RlpError> {
+    let prefix = *data.first().ok_or(RlpError::UnexpectedEnd)?;
+    let (is_list, header_len, payload_len) = match prefix {
+        0x00..=0x7f => (false, 0, 1),
+        0x80..=0xb7 => (false, 1, (prefix - 0x80) as usize),
+        0xb8..=0xbf => {
+            let len_of_len = (prefix - 0xb7) as usize;
+            (false, 1 + len_of_len, read_len(data, len_of_len)?)
+        }
+        0xc0..=0xf7 => (true, 1, (prefix - 0xc0) as usize),
+        0xf8..=0xff => {
+            let len_of_len = (prefix - 0xf7) as usize;
+            (true, 1 + len_of_len, read_len(data, len_of_len)?)
+        }
+    };
+    let total = header_len
+        .checked_add(payload_len)
+        .ok_or(RlpError::LengthOverflow)?;
+    if data.len() < total {
+        return Err(RlpError::UnexpectedEnd);
+    }
+    Ok((is_list, header_len, payload_len))
+}
+
+fn read_len(data: &[u8], len_of_len: usize) -> Result<usize, RlpError> {
+    if len_of_len > std::mem::size_of::<usize>() {
+        return Err(RlpError::LengthOverflow);
+    }
+    let bytes = data.get(1..1 + len_of_len).ok_or(RlpError::UnexpectedEnd)?;
+    Ok(bytes.iter().fold(0, |n, b| n << 8 | *b as usize))
+}
+
+/// Decode the first item of `data`, returning it along with its encoded length
+pub(crate) fn decode_item(data: &[u8]) -> Result<(Item<'_>, usize), RlpError> {
+    let (is_list, header_len, payload_len) = header(data)?;
+    let payload = &data[header_len..header_len + payload_len];
+    let item = if is_list {
+        let mut items = vec![];
+        let mut rest = payload;
+        while !rest.is_empty() {
+            let (item, len) = decode_item(rest)?;
+            items.push(item);
+            rest = &rest[len..];
+        }
+        Item::List(items)
+    } else {
+        Item::Bytes(payload)
+    };
+    Ok((item, header_len + payload_len))
+}
+
+/// Decode a single item spanning the whole input
+pub(crate) fn decode(data: &[u8]) -> Result<Item<'_>, RlpError> {
+    let (item, len) = decode_item(data)?;
+    match data.len() - len {
+        0 => Ok(item),
+        n => Err(RlpError::TrailingBytes(n)),
+    }
+}
+
+/// Encoder of an RLP list
+#[derive(Debug, Default)]
+pub(crate) struct ListEncoder {
+    payload: Vec<u8>,
+}
+
+impl ListEncoder {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a byte string
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        match bytes {
+            [b] if *b < 0x80 => self.payload.push(*b),
+            _ => {
+                push_header(&mut self.payload, 0x80, bytes.len());
+                self.payload.extend_from_slice(bytes);
+            }
+        }
+        self
+    }
+
+    /// Append an unsigned integer, as its minimal big-endian representation
+    pub(crate) fn uint(&mut self, n: impl Into<u128>) -> &mut Self {
+        self.uint_be(&n.into().to_be_bytes())
+    }
+
+    /// Append a big-endian unsigned integer of any size, without its leading
+    /// zeros
+    pub(crate) fn uint_be(&mut self, n: &[u8]) -> &mut Self {
+        let start = n.iter().position(|b| *b != 0).unwrap_or(n.len());
+        self.bytes(&n[start..])
+    }
+
+    /// Append a nested list
+    pub(crate) fn list(&mut self, list: &ListEncoder) -> &mut Self {
+        self.payload.extend_from_slice(&list.finish());
+        self
+    }
+
+    /// Encode the list
+    pub(crate) fn finish(&self) -> Vec<u8> {
+        let mut m = vec![];
+        push_header(&mut m, 0xc0, self.payload.len());
+        m.extend_from_slice(&self.payload);
+        m
+    }
+}
+
+/// Push the header of a byte string (`offset` 0x80) or list (`offset` 0xc0)
+fn push_header(m: &mut Vec<u8>, offset: u8, len: usize) {
+    if len < 56 {
+        m.push(offset + len as u8);
+    } else {
+        let len = len.to_be_bytes();
+        let start = len.iter().position(|b| *b != 0).unwrap_or(len.len());
+        m.push(offset + 55 + (len.len() - start) as u8);
+        m.extend_from_slice(&len[start..]);
+    }
+}
@@ -0,0 +1,259 @@
+//! Signatures returned by the device and their usual encodings
+// https://eips.ethereum.org/EIPS/eip-2098
+
+use std::ops::Deref;
+
+use ledger_transport::{APDUAnswer, APDUErrorCode};
+use ledger_zondax_generic::LedgerAppError;
+use secp256k1::ecdsa;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::types::EthError;
+
+/// Half of the secp256k1 curve order, the largest canonical `s`
+const HALF_ORDER: [u8; 32] = [
+    0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0x5d, 0x57, 0x6e, 0x73, 0x57, 0xa4, 0x50, 0x1d, 0xdf, 0xe9, 0x2f, 0x46, 0x68, 0x1b, 0x20, 0xa0,
+];
+
+/// Signature Error
+#[derive(Debug, thiserror::Error)]
+pub enum SignatureError {
+    /// `v` is none of `yParity`, 27/28 or an EIP-155 `v`
+    #[error("invalid signature v {0}")]
+    InvalidV(u64),
+
+    /// Encoding of the wrong length
+    #[error("invalid signature length {0}")]
+    InvalidLength(usize),
+
+    /// `s` is in the upper half of the curve order
+    #[error("signature s is not canonical")]
+    HighS,
+
+    /// `r` or `s` is not a valid scalar
+    #[error("secp256k1 | {0}")]
+    Secp256k1(#[from] secp256k1::Error),
+
+    /// Hex encoding could not be decoded
+    #[error("invalid hex: {0}")]
+    Hex(#[from] hex::FromHexError),
+}
+
+/// Encoding of `v` in a 65 bytes signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VEncoding {
+    /// 27 or 28, as expected by `ecrecover`
+    Electrum,
+    /// `yParity`, 0 or 1
+    Parity,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signature {
+    /// Recovery value: EIP-155 `v` for legacy transactions, `yParity` for
+    /// typed ones and 27/28 for messages
+    pub v: u64,
+    pub r: [u8; 32],
+    pub s: [u8; 32],
+}
+
+impl Signature {
+    /// Parse a `v || r || s` signature from the last response of a signing
+    /// flow
+    pub(crate) fn from_answer<A, E>(response: &APDUAnswer<A>) -> Result<Self, EthError<E>>
+    where
+        A: Deref<Target = [u8]>,
+        E: std::error::Error,
+    {
+        let response_data = response.data();
+        match response.error_code() {
+            Ok(APDUErrorCode::NoError) if response_data.is_empty() => {
+                return Err(EthError::Ledger(LedgerAppError::NoSignature))
+            }
+            // Last response should contain the answer
+            Ok(APDUErrorCode::NoError) if response_data.len() < 3 => {
+                return Err(EthError::Ledger(LedgerAppError::InvalidSignature))
+            }
+            Ok(APDUErrorCode::NoError) => {}
+            Ok(err) => {
+                return Err(EthError::Ledger(LedgerAppError::AppSpecific(
+                    err as _,
+                    err.description(),
+                )))
+            }
+            Err(err) => {
+                return Err(EthError::Ledger(LedgerAppError::AppSpecific(
+                    err,
+                    "[APDU_ERROR] Unknown".to_string(),
+                )))
+            }
+        }
+
+        let v = response_data
+            .first()
+            .ok_or(EthError::MissingResponseData(
+                "signature v component".into(),
+            ))?
+            .to_owned()
+            .into();
+        let r = response_data
+            .get(1..33)
+            .ok_or(EthError::MissingResponseData(
+                "signature r component".into(),
+            ))?
+            .try_into() // safe due to get() range
+            .unwrap();
+        let s = response_data
+            .get(33..65)
+            .ok_or(EthError::MissingResponseData(
+                "signature s component".into(),
+            ))?
+            .try_into() // safe due to get() range
+            .unwrap();
+        Ok(Signature { v, r, s })
+    }
+
+    /// Parity of the `y` coordinate, from any encoding of `v`
+    pub fn y_parity(&self) -> Result<u8, SignatureError> {
+        match self.v {
+            0 | 1 => Ok(self.v as u8),
+            27 | 28 => Ok((self.v - 27) as u8),
+            v if v >= 35 => Ok(((v - 35) % 2) as u8),
+            v => Err(SignatureError::InvalidV(v)),
+        }
+    }
+
+    /// `r || s || v`, with `v` in a single byte
+    pub fn to_rsv_bytes(self, encoding: VEncoding) -> Result<[u8; 65], SignatureError> {
+        let parity = self.y_parity()?;
+        let mut bytes = [0u8; 65];
+        bytes[..32].copy_from_slice(&self.r);
+        bytes[32..64].copy_from_slice(&self.s);
+        bytes[64] = match encoding {
+            VEncoding::Electrum => 27 + parity,
+            VEncoding::Parity => parity,
+        };
+        Ok(bytes)
+    }
+
+    /// Parse `r || s || v`, keeping `v` as is
+    pub fn from_rsv_bytes(bytes: &[u8]) -> Result<Self, SignatureError> {
+        if bytes.len() != 65 {
+            return Err(SignatureError::InvalidLength(bytes.len()));
+        }
+        let signature = Signature {
+            v: bytes[64].into(),
+            // safe, the length is checked above
+            r: bytes[..32].try_into().unwrap(),
+            s: bytes[32..64].try_into().unwrap(),
+        };
+        signature.y_parity()?;
+        Ok(signature)
+    }
+
+    /// `0x` prefixed hex of [`Signature::to_rsv_bytes`]
+    pub fn to_hex(self, encoding: VEncoding) -> Result<String, SignatureError> {
+        Ok(format!("0x{}", hex::encode(self.to_rsv_bytes(encoding)?)))
+    }
+
+    /// Parse the hex of `r || s || v`, with or without the `0x` prefix
+    pub fn from_hex(s: &str) -> Result<Self, SignatureError> {
+        Self::from_rsv_bytes(&hex::decode(s.strip_prefix("0x").unwrap_or(s))?)
+    }
+
+    /// EIP-2098 compact encoding: `r || yParity << 255 | s`. Only canonical
+    /// signatures can be encoded.
+    pub fn to_eip2098(self) -> Result<[u8; 64], SignatureError> {
+        if !self.is_low_s() {
+            return Err(SignatureError::HighS);
+        }
+        let mut bytes = [0u8; 64];
+        bytes[..32].copy_from_slice(&self.r);
+        bytes[32..].copy_from_slice(&self.s);
+        bytes[32] |= self.y_parity()? << 7;
+        Ok(bytes)
+    }
+
+    /// Parse an EIP-2098 compact signature, with `v` as `yParity`
+    pub fn from_eip2098(bytes: &[u8; 64]) -> Self {
+        let mut s: [u8; 32] = bytes[32..].try_into().unwrap(); // safe, 32 bytes
+        let y_parity = s[0] >> 7;
+        s[0] &= 0x7f;
+        Signature {
+            v: y_parity.into(),
+            r: bytes[..32].try_into().unwrap(), // safe, 32 bytes
+            s,
+        }
+    }
+
+    /// DER encoding of `r` and `s`, as used by most non-Ethereum tooling
+    pub fn to_der(self) -> Result<Vec<u8>, SignatureError> {
+        Ok(self.secp256k1()?.serialize_der().to_vec())
+    }
+
+    /// Whether `s` is in the lower half of the curve order, as required by
+    /// EIP-2
+    pub fn is_low_s(&self) -> bool {
+        self.s <= HALF_ORDER
+    }
+
+    /// Replace a high `s` by its canonical counterpart, flipping the parity of
+    /// `v` to keep the signature valid
+    pub fn normalize_s(&mut self) -> Result<(), SignatureError> {
+        if self.is_low_s() {
+            return Ok(());
+        }
+        let parity = self.y_parity()?;
+        let mut signature = self.secp256k1()?;
+        signature.normalize_s();
+        // safe, the compact encoding is r || s
+        self.s = signature.serialize_compact()[32..].try_into().unwrap();
+        self.v = self.v - parity as u64 + (1 - parity) as u64;
+        Ok(())
+    }
+
+    fn secp256k1(&self) -> Result<ecdsa::Signature, SignatureError> {
+        let mut compact = [0u8; 64];
+        compact[..32].copy_from_slice(&self.r);
+        compact[32..].copy_from_slice(&self.s);
+        Ok(ecdsa::Signature::from_compact(&compact)?)
+    }
+}
+
+/// JSON-RPC style representation: `0x` prefixed `r` and `s`, and `v` as is
+#[derive(Serialize, Deserialize)]
+struct SignatureRepr {
+    v: u64,
+    r: String,
+    s: String,
+}
+
+impl Serialize for Signature {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SignatureRepr {
+            v: self.v,
+            r: format!("0x{}", hex::encode(self.r)),
+            s: format!("0x{}", hex::encode(self.s)),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Signature {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = SignatureRepr::deserialize(deserializer)?;
+        let word = |s: &str| -> Result<[u8; 32], D::Error> {
+            let bytes =
+                hex::decode(s.strip_prefix("0x").unwrap_or(s)).map_err(serde::de::Error::custom)?;
+            bytes
+                .try_into()
+                .map_err(|_| serde::de::Error::custom("expected 32 bytes"))
+        };
+        Ok(Signature {
+            v: repr.v,
+            r: word(&repr.r)?,
+            s: word(&repr.s)?,
+        })
+    }
+}
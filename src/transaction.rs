@@ -0,0 +1,800 @@
+//! Typed transactions and their unsigned encoding, as expected by the app
+// https://eips.ethereum.org/EIPS/eip-155
+// https://eips.ethereum.org/EIPS/eip-2718
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::hash::keccak256;
+use crate::rlp::{self, Item, ListEncoder, RlpError};
+use crate::{Signature, CHUNK_SIZE, MAX_CHUNK_SIZE};
+
+/// Transaction Error
+#[derive(Debug, thiserror::Error)]
+pub enum TransactionError {
+    /// `v` of a signature does not match the transaction
+    #[error("signature v {0} does not match the transaction")]
+    InvalidV(u64),
+
+    /// Chain id too large for the EIP-155 `v` of a legacy transaction
+    #[error("chain id {0} does not fit an EIP-155 v")]
+    ChainIdOverflow(u64),
+
+    /// Transaction is not valid RLP
+    #[error("RLP | {0}")]
+    Rlp(#[from] RlpError),
+
+    /// Unknown EIP-2718 transaction type
+    #[error("unknown transaction type {0:#04x}")]
+    UnknownType(u8),
+
+    /// Unsigned transaction with the wrong number of fields for its type
+    #[error("unexpected number of transaction fields: {0}")]
+    FieldCount(usize),
+}
+
+/// Entry of an EIP-2930 access list
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccessListItem {
+    pub address: [u8; 20],
+    pub storage_keys: Vec<[u8; 32]>,
+}
+
+/// Legacy transaction, replay protected with EIP-155 when `chain_id` is set
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LegacyTx {
+    pub chain_id: Option<u64>,
+    pub nonce: u64,
+    pub gas_price: u128,
+    pub gas_limit: u64,
+    /// Recipient, `None` for a contract creation
+    pub to: Option<[u8; 20]>,
+    pub value: u128,
+    pub data: Vec<u8>,
+}
+
+/// EIP-2930 transaction
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccessListTx {
+    pub chain_id: u64,
+    pub nonce: u64,
+    pub gas_price: u128,
+    pub gas_limit: u64,
+    /// Recipient, `None` for a contract creation
+    pub to: Option<[u8; 20]>,
+    pub value: u128,
+    pub data: Vec<u8>,
+    pub access_list: Vec<AccessListItem>,
+}
+
+/// EIP-1559 transaction
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Eip1559Tx {
+    pub chain_id: u64,
+    pub nonce: u64,
+    pub max_priority_fee_per_gas: u128,
+    pub max_fee_per_gas: u128,
+    pub gas_limit: u64,
+    /// Recipient, `None` for a contract creation
+    pub to: Option<[u8; 20]>,
+    pub value: u128,
+    pub data: Vec<u8>,
+    pub access_list: Vec<AccessListItem>,
+}
+
+/// EIP-4844 blob transaction
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlobTx {
+    pub chain_id: u64,
+    pub nonce: u64,
+    pub max_priority_fee_per_gas: u128,
+    pub max_fee_per_gas: u128,
+    pub gas_limit: u64,
+    /// Recipient, blob transactions cannot create contracts
+    pub to: [u8; 20],
+    pub value: u128,
+    pub data: Vec<u8>,
+    pub access_list: Vec<AccessListItem>,
+    pub max_fee_per_blob_gas: u128,
+    pub blob_versioned_hashes: Vec<[u8; 32]>,
+}
+
+/// Any transaction the app can sign
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypedTx {
+    Legacy(LegacyTx),
+    AccessList(AccessListTx),
+    Eip1559(Eip1559Tx),
+    Blob(BlobTx),
+}
+
+impl From<LegacyTx> for TypedTx {
+    fn from(tx: LegacyTx) -> Self {
+        TypedTx::Legacy(tx)
+    }
+}
+
+impl From<AccessListTx> for TypedTx {
+    fn from(tx: AccessListTx) -> Self {
+        TypedTx::AccessList(tx)
+    }
+}
+
+impl From<Eip1559Tx> for TypedTx {
+    fn from(tx: Eip1559Tx) -> Self {
+        TypedTx::Eip1559(tx)
+    }
+}
+
+impl From<BlobTx> for TypedTx {
+    fn from(tx: BlobTx) -> Self {
+        TypedTx::Blob(tx)
+    }
+}
+
+impl TypedTx {
+    /// EIP-2718 transaction type, `None` for legacy transactions
+    pub fn tx_type(&self) -> Option<u8> {
+        match self {
+            TypedTx::Legacy(_) => None,
+            TypedTx::AccessList(_) => Some(0x01),
+            TypedTx::Eip1559(_) => Some(0x02),
+            TypedTx::Blob(_) => Some(0x03),
+        }
+    }
+
+    /// Chain id, `None` for legacy transactions without EIP-155
+    pub fn chain_id(&self) -> Option<u64> {
+        match self {
+            TypedTx::Legacy(tx) => tx.chain_id,
+            TypedTx::AccessList(tx) => Some(tx.chain_id),
+            TypedTx::Eip1559(tx) => Some(tx.chain_id),
+            TypedTx::Blob(tx) => Some(tx.chain_id),
+        }
+    }
+
+    /// Recipient, `None` for a contract creation
+    pub fn to(&self) -> Option<[u8; 20]> {
+        match self {
+            TypedTx::Legacy(tx) => tx.to,
+            TypedTx::AccessList(tx) => tx.to,
+            TypedTx::Eip1559(tx) => tx.to,
+            TypedTx::Blob(tx) => Some(tx.to),
+        }
+    }
+
+    /// Calldata
+    pub fn data(&self) -> &[u8] {
+        match self {
+            TypedTx::Legacy(tx) => &tx.data,
+            TypedTx::AccessList(tx) => &tx.data,
+            TypedTx::Eip1559(tx) => &tx.data,
+            TypedTx::Blob(tx) => &tx.data,
+        }
+    }
+
+    /// Unsigned payload, as passed to [`crate::EthApp::sign`]: the type byte
+    /// followed by the RLP fields for typed transactions, and the EIP-155
+    /// `chainId, 0, 0` tail for replay protected legacy transactions
+    pub fn encode_unsigned(&self) -> Vec<u8> {
+        let mut fields = self.fields();
+        if let TypedTx::Legacy(LegacyTx {
+            chain_id: Some(chain_id),
+            ..
+        }) = self
+        {
+            fields.uint(*chain_id).uint(0u8).uint(0u8);
+        }
+        self.with_type(fields.finish())
+    }
+
+    /// Hash signed by the device: keccak256 of the unsigned payload
+    pub fn signing_hash(&self) -> [u8; 32] {
+        signing_hash(&self.encode_unsigned())
+    }
+
+    /// Fields shared by the unsigned and signed encodings
+    pub(crate) fn fields(&self) -> ListEncoder {
+        let mut fields = ListEncoder::new();
+        match self {
+            TypedTx::Legacy(tx) => {
+                fields
+                    .uint(tx.nonce)
+                    .uint(tx.gas_price)
+                    .uint(tx.gas_limit)
+                    .bytes(tx.to.as_ref().map(|to| to.as_slice()).unwrap_or_default())
+                    .uint(tx.value)
+                    .bytes(&tx.data);
+            }
+            TypedTx::AccessList(tx) => {
+                fields
+                    .uint(tx.chain_id)
+                    .uint(tx.nonce)
+                    .uint(tx.gas_price)
+                    .uint(tx.gas_limit)
+                    .bytes(tx.to.as_ref().map(|to| to.as_slice()).unwrap_or_default())
+                    .uint(tx.value)
+                    .bytes(&tx.data)
+                    .list(&encode_access_list(&tx.access_list));
+            }
+            TypedTx::Eip1559(tx) => {
+                fields
+                    .uint(tx.chain_id)
+                    .uint(tx.nonce)
+                    .uint(tx.max_priority_fee_per_gas)
+                    .uint(tx.max_fee_per_gas)
+                    .uint(tx.gas_limit)
+                    .bytes(tx.to.as_ref().map(|to| to.as_slice()).unwrap_or_default())
+                    .uint(tx.value)
+                    .bytes(&tx.data)
+                    .list(&encode_access_list(&tx.access_list));
+            }
+            TypedTx::Blob(tx) => {
+                let mut hashes = ListEncoder::new();
+                for hash in &tx.blob_versioned_hashes {
+                    hashes.bytes(hash);
+                }
+                fields
+                    .uint(tx.chain_id)
+                    .uint(tx.nonce)
+                    .uint(tx.max_priority_fee_per_gas)
+                    .uint(tx.max_fee_per_gas)
+                    .uint(tx.gas_limit)
+                    .bytes(&tx.to)
+                    .uint(tx.value)
+                    .bytes(&tx.data)
+                    .list(&encode_access_list(&tx.access_list))
+                    .uint(tx.max_fee_per_blob_gas)
+                    .list(&hashes);
+            }
+        }
+        fields
+    }
+
+    /// Prefix an RLP list with the transaction type, if any
+    pub(crate) fn with_type(&self, rlp: Vec<u8>) -> Vec<u8> {
+        match self.tx_type() {
+            Some(tx_type) => [vec![tx_type], rlp].concat(),
+            None => rlp,
+        }
+    }
+}
+
+impl fmt::Display for TypedTx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.summary().fmt(f)
+    }
+}
+
+impl Serialize for TypedTx {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.summary().serialize(serializer)
+    }
+}
+
+/// Human readable view of a transaction, for logs and previews
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TransactionSummary {
+    /// `legacy`, `eip2930`, `eip1559` or `eip4844`
+    #[serde(rename = "type")]
+    pub tx_type: &'static str,
+    pub chain_id: Option<u64>,
+    pub nonce: u64,
+    /// `0x` prefixed recipient, `None` for a contract creation
+    pub to: Option<String>,
+    /// Value in wei, as a decimal string
+    pub value_wei: String,
+    /// Value in ether, as a decimal string
+    pub value_ether: String,
+    pub gas_limit: u64,
+    /// Gas price in wei, for legacy and EIP-2930 transactions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gas_price: Option<String>,
+    /// Max fee per gas in wei, for EIP-1559 and blob transactions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_fee_per_gas: Option<String>,
+    /// Max priority fee per gas in wei, for EIP-1559 and blob transactions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_priority_fee_per_gas: Option<String>,
+    /// Max fee per blob gas in wei, for blob transactions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_fee_per_blob_gas: Option<String>,
+    pub access_list: Vec<AccessListSummary>,
+    pub data_len: usize,
+    /// `0x` prefixed function selector, if the data holds one
+    pub selector: Option<String>,
+}
+
+/// Access list entry of a [`TransactionSummary`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AccessListSummary {
+    pub address: String,
+    pub storage_keys: Vec<String>,
+}
+
+impl TypedTx {
+    /// Human readable view of the transaction
+    pub fn summary(&self) -> TransactionSummary {
+        let (nonce, gas_limit, to, value, data, access_list) = match self {
+            TypedTx::Legacy(tx) => (tx.nonce, tx.gas_limit, tx.to, tx.value, &tx.data, &[][..]),
+            TypedTx::AccessList(tx) => (
+                tx.nonce,
+                tx.gas_limit,
+                tx.to,
+                tx.value,
+                &tx.data,
+                &tx.access_list[..],
+            ),
+            TypedTx::Eip1559(tx) => (
+                tx.nonce,
+                tx.gas_limit,
+                tx.to,
+                tx.value,
+                &tx.data,
+                &tx.access_list[..],
+            ),
+            TypedTx::Blob(tx) => (
+                tx.nonce,
+                tx.gas_limit,
+                Some(tx.to),
+                tx.value,
+                &tx.data,
+                &tx.access_list[..],
+            ),
+        };
+        let (gas_price, max_fee_per_gas, max_priority_fee_per_gas, max_fee_per_blob_gas) =
+            match self {
+                TypedTx::Legacy(LegacyTx { gas_price, .. })
+                | TypedTx::AccessList(AccessListTx { gas_price, .. }) => {
+                    (Some(*gas_price), None, None, None)
+                }
+                TypedTx::Eip1559(tx) => (
+                    None,
+                    Some(tx.max_fee_per_gas),
+                    Some(tx.max_priority_fee_per_gas),
+                    None,
+                ),
+                TypedTx::Blob(tx) => (
+                    None,
+                    Some(tx.max_fee_per_gas),
+                    Some(tx.max_priority_fee_per_gas),
+                    Some(tx.max_fee_per_blob_gas),
+                ),
+            };
+
+        TransactionSummary {
+            tx_type: match self {
+                TypedTx::Legacy(_) => "legacy",
+                TypedTx::AccessList(_) => "eip2930",
+                TypedTx::Eip1559(_) => "eip1559",
+                TypedTx::Blob(_) => "eip4844",
+            },
+            chain_id: self.chain_id(),
+            nonce,
+            to: to.map(|to| to_hex(&to)),
+            value_wei: value.to_string(),
+            value_ether: format_ether(value),
+            gas_limit,
+            gas_price: gas_price.map(|n| n.to_string()),
+            max_fee_per_gas: max_fee_per_gas.map(|n| n.to_string()),
+            max_priority_fee_per_gas: max_priority_fee_per_gas.map(|n| n.to_string()),
+            max_fee_per_blob_gas: max_fee_per_blob_gas.map(|n| n.to_string()),
+            access_list: access_list
+                .iter()
+                .map(|item| AccessListSummary {
+                    address: to_hex(&item.address),
+                    storage_keys: item.storage_keys.iter().map(|key| to_hex(key)).collect(),
+                })
+                .collect(),
+            data_len: data.len(),
+            selector: data.get(..4).map(to_hex),
+        }
+    }
+}
+
+impl fmt::Display for TransactionSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} transaction", self.tx_type)?;
+        if let Some(chain_id) = self.chain_id {
+            write!(f, " on chain {chain_id}")?;
+        }
+        write!(f, ", nonce {}", self.nonce)?;
+        match &self.to {
+            Some(to) => write!(f, ", to {to}")?,
+            None => write!(f, ", contract creation")?,
+        }
+        write!(
+            f,
+            ", value {} ETH ({} wei), gas limit {}",
+            self.value_ether, self.value_wei, self.gas_limit
+        )?;
+        if let Some(gas_price) = &self.gas_price {
+            write!(f, ", gas price {gas_price} wei")?;
+        }
+        if let Some(max_fee) = &self.max_fee_per_gas {
+            write!(f, ", max fee {max_fee} wei")?;
+        }
+        if let Some(priority_fee) = &self.max_priority_fee_per_gas {
+            write!(f, ", priority fee {priority_fee} wei")?;
+        }
+        if let Some(blob_fee) = &self.max_fee_per_blob_gas {
+            write!(f, ", blob fee {blob_fee} wei")?;
+        }
+        if !self.access_list.is_empty() {
+            write!(f, ", {} access list entries", self.access_list.len())?;
+        }
+        write!(f, ", {} bytes of data", self.data_len)?;
+        if let Some(selector) = &self.selector {
+            write!(f, " (selector {selector})")?;
+        }
+        Ok(())
+    }
+}
+
+/// Decode an unsigned transaction, as passed to [`crate::EthApp::sign`]
+pub fn decode(raw_tx: &[u8]) -> Result<TypedTx, TransactionError> {
+    let (tx_type, rlp) = match raw_tx.first() {
+        Some(&tx_type @ 0x01..=0x03) => (Some(tx_type), &raw_tx[1..]),
+        Some(&tx_type @ 0x00..=0x7f) => return Err(TransactionError::UnknownType(tx_type)),
+        _ => (None, raw_tx),
+    };
+    let item = rlp::decode(rlp)?;
+    let fields = item.as_list()?;
+
+    let tx = match (tx_type, fields) {
+        (None, [nonce, gas_price, gas_limit, to, value, data, rest @ ..]) => {
+            let chain_id = match rest {
+                [] => None,
+                [chain_id, _, _] => Some(chain_id.as_u64()?),
+                _ => return Err(TransactionError::FieldCount(fields.len())),
+            };
+            TypedTx::Legacy(LegacyTx {
+                chain_id,
+                nonce: nonce.as_u64()?,
+                gas_price: gas_price.as_u128()?,
+                gas_limit: gas_limit.as_u64()?,
+                to: to.as_address()?,
+                value: value.as_u128()?,
+                data: data.as_bytes()?.to_vec(),
+            })
+        }
+        (Some(0x01), [chain_id, nonce, gas_price, gas_limit, to, value, data, access_list]) => {
+            TypedTx::AccessList(AccessListTx {
+                chain_id: chain_id.as_u64()?,
+                nonce: nonce.as_u64()?,
+                gas_price: gas_price.as_u128()?,
+                gas_limit: gas_limit.as_u64()?,
+                to: to.as_address()?,
+                value: value.as_u128()?,
+                data: data.as_bytes()?.to_vec(),
+                access_list: decode_access_list(access_list)?,
+            })
+        }
+        (
+            Some(0x02),
+            [chain_id, nonce, max_priority_fee_per_gas, max_fee_per_gas, gas_limit, to, value, data, access_list],
+        ) => TypedTx::Eip1559(Eip1559Tx {
+            chain_id: chain_id.as_u64()?,
+            nonce: nonce.as_u64()?,
+            max_priority_fee_per_gas: max_priority_fee_per_gas.as_u128()?,
+            max_fee_per_gas: max_fee_per_gas.as_u128()?,
+            gas_limit: gas_limit.as_u64()?,
+            to: to.as_address()?,
+            value: value.as_u128()?,
+            data: data.as_bytes()?.to_vec(),
+            access_list: decode_access_list(access_list)?,
+        }),
+        (
+            Some(0x03),
+            [chain_id, nonce, max_priority_fee_per_gas, max_fee_per_gas, gas_limit, to, value, data, access_list, max_fee_per_blob_gas, blob_versioned_hashes],
+        ) => TypedTx::Blob(BlobTx {
+            chain_id: chain_id.as_u64()?,
+            nonce: nonce.as_u64()?,
+            max_priority_fee_per_gas: max_priority_fee_per_gas.as_u128()?,
+            max_fee_per_gas: max_fee_per_gas.as_u128()?,
+            gas_limit: gas_limit.as_u64()?,
+            to: to.as_array()?,
+            value: value.as_u128()?,
+            data: data.as_bytes()?.to_vec(),
+            access_list: decode_access_list(access_list)?,
+            max_fee_per_blob_gas: max_fee_per_blob_gas.as_u128()?,
+            blob_versioned_hashes: blob_versioned_hashes
+                .as_list()?
+                .iter()
+                .map(Item::as_array)
+                .collect::<Result<_, _>>()?,
+        }),
+        _ => return Err(TransactionError::FieldCount(fields.len())),
+    };
+    Ok(tx)
+}
+
+/// Transaction ready to be broadcast
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedTransaction {
+    /// Signed transaction
+    pub tx: TypedTx,
+    /// Encoded `v` (legacy) or `yParity` (typed)
+    pub v: u64,
+    pub r: [u8; 32],
+    pub s: [u8; 32],
+    /// Signed encoding, as passed to `eth_sendRawTransaction`
+    pub raw: Vec<u8>,
+    /// Transaction hash
+    pub hash: [u8; 32],
+}
+
+impl SignedTransaction {
+    /// Assemble a signed transaction from the unsigned one and the signature
+    /// returned by the device
+    pub fn from_parts(
+        unsigned: TypedTx,
+        signature: Signature,
+    ) -> Result<SignedTransaction, TransactionError> {
+        let (tx_type, chain_id) = (unsigned.tx_type(), unsigned.chain_id());
+        let v = full_v(tx_type, chain_id, y_parity(tx_type, chain_id, signature.v)?)?;
+
+        let mut fields = unsigned.fields();
+        fields.uint(v).uint_be(&signature.r).uint_be(&signature.s);
+        let raw = unsigned.with_type(fields.finish());
+        let hash = keccak256(&raw);
+
+        Ok(SignedTransaction {
+            tx: unsigned,
+            v,
+            r: signature.r,
+            s: signature.s,
+            raw,
+            hash,
+        })
+    }
+}
+
+/// Hash signed by the device for an unsigned transaction, as passed to
+/// [`crate::EthApp::sign`]
+pub fn signing_hash(raw_tx: &[u8]) -> [u8; 32] {
+    keccak256(raw_tx)
+}
+
+/// Type and chain id of an unsigned transaction, as passed to
+/// [`crate::EthApp::sign`]
+pub(crate) fn type_and_chain_id(
+    raw_tx: &[u8],
+) -> Result<(Option<u8>, Option<u64>), TransactionError> {
+    match raw_tx.first() {
+        Some(&tx_type @ 0x01..=0x03) => {
+            let item = rlp::decode(&raw_tx[1..])?;
+            let chain_id = item.as_list()?.first().ok_or(RlpError::UnexpectedEnd)?;
+            Ok((Some(tx_type), Some(chain_id.as_u64()?)))
+        }
+        Some(&tx_type @ 0x00..=0x7f) => Err(TransactionError::UnknownType(tx_type)),
+        _ => {
+            let item = rlp::decode(raw_tx)?;
+            // without EIP-155 there is no chain id
+            let chain_id = match item.as_list()?.get(6) {
+                Some(chain_id) => Some(chain_id.as_u64()?),
+                None => None,
+            };
+            Ok((None, chain_id))
+        }
+    }
+}
+
+/// Offset of the EIP-155 `chainId, 0, 0` tail of an unsigned legacy
+/// transaction, `None` for typed transactions and legacy ones without it
+pub(crate) fn eip155_tail_offset(raw_tx: &[u8]) -> Result<Option<usize>, TransactionError> {
+    if matches!(raw_tx.first(), Some(0x00..=0x7f)) {
+        return Ok(None);
+    }
+    let (_, header_len, payload_len) = rlp::header(raw_tx)?;
+    let mut offset = header_len;
+    for _ in 0..6 {
+        if offset == header_len + payload_len {
+            return Ok(None);
+        }
+        offset += rlp::decode_item(&raw_tx[offset..])?.1;
+    }
+    Ok((offset < header_len + payload_len).then_some(offset))
+}
+
+/// Split the data of a SIGN TRANSACTION command along the transaction
+/// structure. The app fails when a chunk boundary lands inside the EIP-155
+/// tail of a legacy transaction, or right before it, so the chunk reaching it
+/// is extended to the end, or cut short when that would not fit in an APDU.
+pub(crate) fn sign_chunks(data: &[u8], tail_start: Option<usize>) -> Vec<Vec<u8>> {
+    let mut chunks = vec![];
+    let mut start = 0;
+    while start < data.len() {
+        let mut end = (start + CHUNK_SIZE).min(data.len());
+        if let Some(tail_start) = tail_start {
+            if end < data.len() && end >= tail_start {
+                end = if data.len() - start <= MAX_CHUNK_SIZE {
+                    data.len()
+                } else {
+                    tail_start - 1
+                };
+            }
+        }
+        chunks.push(data[start..end].to_vec());
+        start = end;
+    }
+    chunks
+}
+
+/// Parity of the signature `y` coordinate. The app only returns the low byte
+/// of `v`, which for legacy transactions is `chainId * 2 + 35 + parity` and
+/// overflows for chain ids above 109, so the parity is recovered modulo 256.
+pub(crate) fn y_parity(
+    tx_type: Option<u8>,
+    chain_id: Option<u64>,
+    v: u64,
+) -> Result<u8, TransactionError> {
+    let offset = match (tx_type, chain_id) {
+        (None, Some(chain_id)) => chain_id.wrapping_mul(2).wrapping_add(35),
+        (None, None) => 27,
+        // some app versions return 27/28 for typed transactions too
+        (Some(_), _) if v >= 27 => 27,
+        (Some(_), _) => 0,
+    };
+    let parity = if v > 0xff {
+        v.wrapping_sub(offset)
+    } else {
+        (v as u8).wrapping_sub(offset as u8).into()
+    };
+    match parity {
+        0 | 1 => Ok(parity as u8),
+        _ => Err(TransactionError::InvalidV(v)),
+    }
+}
+
+/// Full `v` of a signature: EIP-155 `v` for legacy transactions, `yParity` for
+/// typed ones
+pub(crate) fn full_v(
+    tx_type: Option<u8>,
+    chain_id: Option<u64>,
+    y_parity: u8,
+) -> Result<u64, TransactionError> {
+    match (tx_type, chain_id) {
+        (None, Some(chain_id)) => chain_id
+            .checked_mul(2)
+            .and_then(|v| v.checked_add(35 + y_parity as u64))
+            .ok_or(TransactionError::ChainIdOverflow(chain_id)),
+        (None, None) => Ok(27 + y_parity as u64),
+        (Some(_), _) => Ok(y_parity.into()),
+    }
+}
+
+fn encode_access_list(access_list: &[AccessListItem]) -> ListEncoder {
+    let mut list = ListEncoder::new();
+    for item in access_list {
+        let mut keys = ListEncoder::new();
+        for key in &item.storage_keys {
+            keys.bytes(key);
+        }
+        let mut entry = ListEncoder::new();
+        entry.bytes(&item.address).list(&keys);
+        list.list(&entry);
+    }
+    list
+}
+
+fn decode_access_list(item: &Item) -> Result<Vec<AccessListItem>, RlpError> {
+    item.as_list()?
+        .iter()
+        .map(|entry| match entry.as_list()? {
+            [address, storage_keys] => Ok(AccessListItem {
+                address: address.as_array()?,
+                storage_keys: storage_keys
+                    .as_list()?
+                    .iter()
+                    .map(Item::as_array)
+                    .collect::<Result<_, _>>()?,
+            }),
+            _ => Err(RlpError::Unexpected("access list entry")),
+        })
+        .collect()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+/// Format an amount of wei in ether, without trailing zeros
+fn format_ether(wei: u128) -> String {
+    const WEI_PER_ETHER: u128 = 1_000_000_000_000_000_000;
+    let (whole, fraction) = (wei / WEI_PER_ETHER, wei % WEI_PER_ETHER);
+    if fraction == 0 {
+        return whole.to_string();
+    }
+    let fraction = format!("{fraction:018}");
+    format!("{whole}.{}", fraction.trim_end_matches('0'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DerivationPath;
+
+    /// Chain id encoded on 8 bytes, for an 11 bytes EIP-155 tail
+    const LONG_CHAIN_ID: u64 = 0x0102_0304_0506_0708;
+
+    /// SIGN TRANSACTION data of a legacy transaction with `data_len` bytes of
+    /// calldata, and the offset of its EIP-155 tail in it
+    fn sign_data(chain_id: u64, data_len: usize) -> (Vec<u8>, usize) {
+        let path = "m/44'/60'/0'/0/0"
+            .parse::<DerivationPath>()
+            .unwrap()
+            .serialize();
+        let raw_tx = TypedTx::from(LegacyTx {
+            chain_id: Some(chain_id),
+            data: vec![0xab; data_len],
+            ..Default::default()
+        })
+        .encode_unsigned();
+        let offset = eip155_tail_offset(&raw_tx).unwrap().unwrap();
+        ([&path[..], &raw_tx].concat(), path.len() + offset)
+    }
+
+    /// SIGN TRANSACTION data whose EIP-155 tail starts at `tail_start`
+    fn sign_data_with_tail_at(chain_id: u64, tail_start: usize) -> Vec<u8> {
+        (0..tail_start)
+            .map(|data_len| sign_data(chain_id, data_len))
+            .find(|(_, start)| *start == tail_start)
+            .expect("no calldata length puts the tail there")
+            .0
+    }
+
+    /// Split `data`, checking that the chunks rebuild it, fit in an APDU and
+    /// never start inside the tail
+    fn chunk_lens(data: &[u8], tail_start: usize) -> Vec<usize> {
+        let chunks = sign_chunks(data, Some(tail_start));
+        assert_eq!(data, chunks.concat());
+        let mut start = 0;
+        for chunk in &chunks {
+            assert!(!chunk.is_empty() && chunk.len() <= MAX_CHUNK_SIZE);
+            assert!(start < tail_start, "a chunk starts inside the tail");
+            start += chunk.len();
+        }
+        chunks.iter().map(Vec::len).collect()
+    }
+
+    #[test]
+    fn extends_the_chunk_reaching_the_tail() {
+        // the tail would start the second chunk
+        let data = sign_data_with_tail_at(1, CHUNK_SIZE);
+        assert_eq!(vec![CHUNK_SIZE + 3], chunk_lens(&data, CHUNK_SIZE));
+
+        // the transaction ends exactly on the APDU limit
+        let tail_start = MAX_CHUNK_SIZE - 11;
+        let data = sign_data_with_tail_at(LONG_CHAIN_ID, tail_start);
+        assert_eq!(MAX_CHUNK_SIZE, data.len());
+        assert_eq!(vec![MAX_CHUNK_SIZE], chunk_lens(&data, tail_start));
+    }
+
+    #[test]
+    fn cuts_the_chunk_before_the_tail() {
+        // the transaction ends one byte after the APDU limit
+        let tail_start = MAX_CHUNK_SIZE - 10;
+        let data = sign_data_with_tail_at(LONG_CHAIN_ID, tail_start);
+        assert_eq!(MAX_CHUNK_SIZE + 1, data.len());
+        assert_eq!(vec![tail_start - 1, 12], chunk_lens(&data, tail_start));
+
+        let tail_start = CHUNK_SIZE - 2;
+        let data = sign_data_with_tail_at(LONG_CHAIN_ID, tail_start);
+        assert_eq!(vec![tail_start - 1, 12], chunk_lens(&data, tail_start));
+    }
+
+    #[test]
+    fn splits_any_transaction_size() {
+        let data = sign_data_with_tail_at(1, CHUNK_SIZE + 1);
+        assert_eq!(vec![CHUNK_SIZE, 4], chunk_lens(&data, CHUNK_SIZE + 1));
+
+        for chain_id in [1, LONG_CHAIN_ID] {
+            for data_len in 0..800 {
+                let (data, tail_start) = sign_data(chain_id, data_len);
+                chunk_lens(&data, tail_start);
+            }
+        }
+    }
+}
@@ -1,5 +1,14 @@
+use std::fmt;
+use std::str::FromStr;
+
 use ledger_zondax_generic::LedgerAppError;
 
+use crate::address::{AddressError, EthAddress};
+use crate::bip32::Bip32Error;
+use crate::eip712::Eip712Error;
+use crate::transaction::{TransactionError, TransactionSummary};
+use crate::verification::VerificationError;
+
 /// Ethereum Ledger Error
 #[derive(Debug, thiserror::Error)]
 pub enum EthError<E: std::error::Error> {
@@ -7,6 +16,57 @@ pub enum EthError<E: std::error::Error> {
     /// Common Ledger errors
     Ledger(#[from] LedgerAppError<E>),
 
+    /// Invalid EIP-712 typed data
+    #[error("EIP-712 | {0}")]
+    Eip712(#[from] Eip712Error),
+
+    /// Invalid signed descriptor
+    #[error("Descriptor | {0}")]
+    Descriptor(#[from] DescriptorError),
+
+    /// Signed descriptor rejected by local verification
+    #[error("Verification | {0}")]
+    Verification(#[from] VerificationError),
+
+    /// Signature that could not be verified locally
+    #[cfg(feature = "recovery")]
+    #[error("Recovery | {0}")]
+    Recovery(#[from] crate::recovery::RecoveryError),
+
+    /// Invalid address
+    #[error("Address | {0}")]
+    Address(#[from] AddressError),
+
+    /// The address reported by the device is not the one of its public key
+    #[error("device reported address {reported}, but its public key is {derived}")]
+    AddressMismatch {
+        reported: EthAddress,
+        derived: EthAddress,
+    },
+
+    /// Extended public key derivation failed
+    #[error("BIP32 | {0}")]
+    Bip32(#[from] Bip32Error),
+
+    /// Invalid derivation path
+    #[error("Path | {0}")]
+    Path(#[from] PathError),
+
+    /// Invalid transaction
+    #[error("Transaction | {0}")]
+    Transaction(#[from] TransactionError),
+
+    /// Signing a transaction failed, e.g. the user rejected it
+    #[error("Failed to sign {summary}: {}", &**error)]
+    Signing {
+        summary: Box<TransactionSummary>,
+        error: Box<EthError<E>>,
+    },
+
+    /// A descriptor of a transaction resolution could not be provided
+    #[error("Failed to provide {descriptor}: {reason}")]
+    ResolutionRejected { descriptor: String, reason: String },
+
     /// Missing response data part
     #[error("Missing response data: {0}")]
     MissingResponseData(String),
@@ -16,6 +76,97 @@ pub enum EthError<E: std::error::Error> {
     Other(String),
 }
 
+/// Error while serializing or parsing a signed descriptor
+#[derive(Debug, thiserror::Error)]
+pub enum DescriptorError {
+    /// Descriptor ended before the given field
+    #[error("missing {0}")]
+    Missing(&'static str),
+
+    /// A field does not fit its length prefix
+    #[error("{0} is too long")]
+    TooLong(&'static str),
+
+    /// Unexpected descriptor type or version
+    #[error("unsupported {0}: {1}")]
+    Unsupported(&'static str, u8),
+
+    /// Trailing bytes after the signature
+    #[error("{0} trailing bytes")]
+    TrailingBytes(usize),
+
+    /// A text field is not valid UTF-8
+    #[error("{0} is not valid UTF-8")]
+    InvalidUtf8(&'static str),
+
+    /// Hex payload could not be decoded
+    #[error("invalid hex: {0}")]
+    Hex(#[from] hex::FromHexError),
+}
+
+/// Cursor over a descriptor payload
+pub(crate) struct DescriptorReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> DescriptorReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        DescriptorReader { data }
+    }
+
+    pub(crate) fn bytes(
+        &mut self,
+        len: usize,
+        what: &'static str,
+    ) -> Result<&'a [u8], DescriptorError> {
+        if self.data.len() < len {
+            return Err(DescriptorError::Missing(what));
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    pub(crate) fn u8(&mut self, what: &'static str) -> Result<u8, DescriptorError> {
+        Ok(self.bytes(1, what)?[0])
+    }
+
+    pub(crate) fn u32(&mut self, what: &'static str) -> Result<u32, DescriptorError> {
+        Ok(u32::from_be_bytes(self.bytes(4, what)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn u64(&mut self, what: &'static str) -> Result<u64, DescriptorError> {
+        Ok(u64::from_be_bytes(self.bytes(8, what)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn address(&mut self, what: &'static str) -> Result<[u8; 20], DescriptorError> {
+        Ok(self.bytes(20, what)?.try_into().unwrap())
+    }
+
+    /// A byte string prefixed with its length on one byte
+    pub(crate) fn short_bytes(&mut self, what: &'static str) -> Result<&'a [u8], DescriptorError> {
+        let len = self.u8(what)?;
+        self.bytes(len.into(), what)
+    }
+
+    /// A UTF-8 string prefixed with its length on one byte
+    pub(crate) fn short_str(&mut self, what: &'static str) -> Result<String, DescriptorError> {
+        String::from_utf8(self.short_bytes(what)?.to_vec())
+            .map_err(|_| DescriptorError::InvalidUtf8(what))
+    }
+
+    pub(crate) fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.data)
+    }
+
+    pub(crate) fn finish(self) -> Result<(), DescriptorError> {
+        match self.data.len() {
+            0 => Ok(()),
+            n => Err(DescriptorError::TrailingBytes(n)),
+        }
+    }
+}
+
 /// Chunk payload type
 pub enum ChunkPayloadType {
     /// First chunk
@@ -25,7 +176,7 @@ pub enum ChunkPayloadType {
 }
 
 /// BIP44 Path
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BIP44Path {
     /// Purpose
     pub purpose: u32,
@@ -56,7 +207,253 @@ impl BIP44Path {
     }
 }
 
-#[derive(Debug)]
+impl fmt::Display for BIP44Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        DerivationPath::from(self).fmt(f)
+    }
+}
+
+impl FromStr for BIP44Path {
+    type Err = PathError;
+
+    /// Parse a 5 components path, e.g. `m/44'/60'/0'/0/0`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match DerivationPath::from_str(s)?.components[..] {
+            [purpose, coin, account, change, index] => Ok(BIP44Path {
+                purpose,
+                coin,
+                account,
+                change,
+                index,
+            }),
+            ref components => Err(PathError::Length(components.len())),
+        }
+    }
+}
+
+/// Error while building or parsing a derivation path
+#[derive(Debug, thiserror::Error)]
+pub enum PathError {
+    /// A path must have between 1 and 10 components
+    #[error("derivation path has {0} components, expected 1 to 10")]
+    Length(usize),
+
+    /// A component is not a 31 bits integer, optionally hardened
+    #[error("invalid derivation path component {0:?}")]
+    InvalidComponent(String),
+
+    /// A path template without the `x` account index placeholder
+    #[error("derivation path template {0:?} has no x placeholder")]
+    Template(String),
+}
+
+/// BIP32 derivation path of 1 to 10 components, e.g. `m/44'/60'/0'/0/0`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DerivationPath {
+    /// Path components, with the hardened bit set
+    components: Vec<u32>,
+}
+
+impl DerivationPath {
+    /// Bit set on hardened components
+    pub const HARDENED: u32 = 0x8000_0000;
+    /// Maximum number of components accepted by the app
+    pub const MAX_COMPONENTS: usize = 10;
+
+    /// Create a path from raw components, with the hardened bit set
+    pub fn new(components: Vec<u32>) -> Result<Self, PathError> {
+        match components.len() {
+            1..=Self::MAX_COMPONENTS => Ok(DerivationPath { components }),
+            len => Err(PathError::Length(len)),
+        }
+    }
+
+    /// Raw components, with the hardened bit set
+    pub fn components(&self) -> &[u32] {
+        &self.components
+    }
+
+    /// Serialize a [`DerivationPath`] in the format used in the app
+    pub fn serialize(&self) -> Vec<u8> {
+        use byteorder::{BigEndian, WriteBytesExt};
+        let mut m = Vec::new();
+
+        m.write_u8(self.components.len() as u8).unwrap(); // number of path components
+        for component in &self.components {
+            m.write_u32::<BigEndian>(*component).unwrap();
+        }
+
+        m
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "m")?;
+        for component in &self.components {
+            match component & Self::HARDENED {
+                0 => write!(f, "/{component}")?,
+                _ => write!(f, "/{}'", component & !Self::HARDENED)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = PathError;
+
+    /// Parse `m/44'/60'/0'/0/0`, where `h` or `H` may replace `'` and the
+    /// `m/` prefix is optional
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.strip_prefix("m/").unwrap_or(s);
+        let components = s
+            .split('/')
+            .map(|component| {
+                let (index, hardened) =
+                    match component.strip_suffix(|c: char| matches!(c, '\'' | 'h' | 'H')) {
+                        Some(index) => (index, DerivationPath::HARDENED),
+                        None => (component, 0),
+                    };
+                match index.parse::<u32>() {
+                    // u32 parsing accepts a leading `+`
+                    Ok(n) if n < DerivationPath::HARDENED && !index.starts_with('+') => {
+                        Ok(n | hardened)
+                    }
+                    _ => Err(PathError::InvalidComponent(component.to_string())),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        DerivationPath::new(components)
+    }
+}
+
+/// Keep the raw values of a [`BIP44Path`], hardened bits included
+impl From<&BIP44Path> for DerivationPath {
+    fn from(path: &BIP44Path) -> Self {
+        DerivationPath {
+            components: vec![
+                path.purpose,
+                path.coin,
+                path.account,
+                path.change,
+                path.index,
+            ],
+        }
+    }
+}
+
+impl From<BIP44Path> for DerivationPath {
+    fn from(path: BIP44Path) -> Self {
+        DerivationPath::from(&path)
+    }
+}
+
+impl From<&Eth2Path> for DerivationPath {
+    fn from(path: &Eth2Path) -> Self {
+        DerivationPath {
+            components: path.components.clone(),
+        }
+    }
+}
+
+impl From<Eth2Path> for DerivationPath {
+    fn from(path: Eth2Path) -> Self {
+        DerivationPath {
+            components: path.components,
+        }
+    }
+}
+
+impl From<&DerivationPath> for DerivationPath {
+    fn from(path: &DerivationPath) -> Self {
+        path.clone()
+    }
+}
+
+/// Path layout used by a wallet to derive its accounts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathScheme {
+    /// Ledger Live: `m/44'/60'/x'/0/0`
+    LedgerLive,
+    /// Legacy Ledger Chrome app, MEW and MyCrypto: `m/44'/60'/0'/x`
+    Legacy,
+    /// BIP44 standard, as used by MetaMask: `m/44'/60'/0'/0/x`
+    Bip44,
+    /// Custom template, where `x` is replaced by the account index, e.g.
+    /// `m/44'/60'/x'`
+    Custom(String),
+}
+
+impl PathScheme {
+    /// Template of the scheme, `x` standing for the account index
+    pub fn template(&self) -> &str {
+        match self {
+            PathScheme::LedgerLive => "m/44'/60'/x'/0/0",
+            PathScheme::Legacy => "m/44'/60'/0'/x",
+            PathScheme::Bip44 => "m/44'/60'/0'/0/x",
+            PathScheme::Custom(template) => template,
+        }
+    }
+
+    /// Path of account `index`
+    pub fn path(&self, index: u32) -> Result<DerivationPath, PathError> {
+        let template = self.template();
+        if !template.contains('x') {
+            return Err(PathError::Template(template.to_string()));
+        }
+        template.replace('x', &index.to_string()).parse()
+    }
+}
+
+/// EIP-2334 BLS12-381 key path of 1 to 10 components, e.g.
+/// `m/12381/3600/0/0/0`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Eth2Path {
+    /// Path components, without hardening
+    components: Vec<u32>,
+}
+
+impl Eth2Path {
+    /// Purpose of EIP-2334 paths
+    pub const PURPOSE: u32 = 12381;
+    /// Coin type of Ethereum in EIP-2334 paths
+    pub const COIN: u32 = 3600;
+
+    /// Create a path from raw components
+    pub fn new(components: Vec<u32>) -> Result<Self, PathError> {
+        match components.len() {
+            1..=DerivationPath::MAX_COMPONENTS => Ok(Eth2Path { components }),
+            len => Err(PathError::Length(len)),
+        }
+    }
+
+    /// Raw components
+    pub fn components(&self) -> &[u32] {
+        &self.components
+    }
+
+    /// Signing key of validator `index`: `m/12381/3600/index/0/0`
+    pub fn signing(index: u32) -> Self {
+        Eth2Path {
+            components: vec![Self::PURPOSE, Self::COIN, index, 0, 0],
+        }
+    }
+
+    /// Withdrawal key of validator `index`: `m/12381/3600/index/0`
+    pub fn withdrawal(index: u32) -> Self {
+        Eth2Path {
+            components: vec![Self::PURPOSE, Self::COIN, index, 0],
+        }
+    }
+
+    /// Serialize an [`Eth2Path`] in the format used in the app
+    pub fn serialize_eth2(&self) -> Vec<u8> {
+        DerivationPath::from(self).serialize()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct LedgerEthTransactionResolution {
     /// Device serialized data that contains ERC20 data (hex format)
     pub erc20_tokens: Vec<String>,
@@ -68,8 +465,12 @@ pub struct LedgerEthTransactionResolution {
     pub plugin: Vec<String>,
 }
 
-#[derive(Debug)]
+/// Ledger-signed binding of an external plugin to a contract method
+#[derive(Debug, Clone)]
 pub struct ExternalPluginData {
+    /// Plugin name length || plugin name || contract address || selector (hex
+    /// format)
     pub payload: String,
+    /// DER signature of the payload (hex format)
     pub signature: String,
 }
@@ -0,0 +1,316 @@
+//! Offline verification of Ledger-signed descriptors against the CAL (crypto
+//! asset list) public keys, so a bad payload fails before reaching the device
+
+use std::fmt;
+
+use secp256k1::ecdsa::Signature;
+use secp256k1::{Message, PublicKey, SECP256K1};
+use sha2::{Digest, Sha256};
+
+use crate::types::{DescriptorError, ExternalPluginData};
+use crate::{Erc20TokenInfo, NftInfo, PluginInfo};
+
+/// Production CAL key
+// production key 2019-01-11 (erc20signer)
+const PRODUCTION_KEY: [u8; 65] = [
+    0x04, 0x5e, 0x6c, 0x10, 0x20, 0xc1, 0x4d, 0xc4, 0x64, 0x42, 0xfe, 0x89, 0xf9, 0x7c, 0x0b, 0x68,
+    0xcd, 0xb1, 0x59, 0x76, 0xdc, 0x24, 0xf2, 0x4c, 0x31, 0x6e, 0x7b, 0x30, 0xfe, 0x4e, 0x8c, 0xc7,
+    0x6b, 0x14, 0x89, 0x15, 0x0c, 0x21, 0x51, 0x4e, 0xbf, 0x44, 0x0f, 0xf5, 0xde, 0xa5, 0x39, 0x3d,
+    0x83, 0xde, 0x53, 0x58, 0xcd, 0x09, 0x8f, 0xce, 0x8f, 0xd0, 0xf8, 0x1d, 0xaa, 0x94, 0x97, 0x91,
+    0x83,
+];
+
+/// Test CAL key
+// https://github.com/LedgerHQ/app-ethereum/blob/develop/doc/ethapp.adoc#provide-erc-20-token-information
+const TEST_KEY: [u8; 65] = [
+    0x04, 0x82, 0xbb, 0xf2, 0xf3, 0x4f, 0x36, 0x7b, 0x2e, 0x5b, 0xc2, 0x18, 0x47, 0xb6, 0x56, 0x6f,
+    0x21, 0xf0, 0x97, 0x6b, 0x22, 0xd3, 0x38, 0x8a, 0x9a, 0x5e, 0x44, 0x6a, 0xc6, 0x2d, 0x25, 0xcf,
+    0x72, 0x5b, 0x62, 0xa2, 0x55, 0x5b, 0x2d, 0xd4, 0x64, 0xa4, 0xda, 0x0a, 0xb2, 0xf4, 0xd5, 0x06,
+    0x82, 0x05, 0x43, 0xaf, 0x1d, 0x24, 0x24, 0x70, 0xb1, 0xb1, 0xa9, 0x69, 0xa2, 0x75, 0x78, 0xf3,
+    0x53,
+];
+
+/// CAL environment the descriptors were signed for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalEnvironment {
+    /// Descriptors served to production apps
+    Production,
+    /// Descriptors served to apps built with the test key
+    Test,
+}
+
+impl CalEnvironment {
+    /// CAL public key of this environment
+    pub fn public_key(&self) -> PublicKey {
+        let key = match self {
+            CalEnvironment::Production => &PRODUCTION_KEY,
+            CalEnvironment::Test => &TEST_KEY,
+        };
+        // safe, both keys are valid points
+        PublicKey::from_slice(key).unwrap()
+    }
+}
+
+/// Kind of signed descriptor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DescriptorKind {
+    Erc20,
+    Nft,
+    ExternalPlugin,
+    Plugin,
+}
+
+impl fmt::Display for DescriptorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DescriptorKind::Erc20 => "ERC 20 token",
+            DescriptorKind::Nft => "NFT",
+            DescriptorKind::ExternalPlugin => "external plugin",
+            DescriptorKind::Plugin => "plugin",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Descriptor Verification Error
+#[derive(Debug, thiserror::Error)]
+pub enum VerificationError {
+    /// Signature is not a valid DER signature
+    #[error("malformed {0} signature")]
+    MalformedSignature(DescriptorKind),
+
+    /// No key is trusted for this kind of descriptor
+    #[error("no CAL key to verify {0} descriptors")]
+    MissingKey(DescriptorKind),
+
+    /// Signature does not match the CAL key
+    #[error("{0} descriptor is not signed by the CAL key")]
+    InvalidSignature(DescriptorKind),
+
+    /// Descriptor could not be decoded
+    #[error("{0}")]
+    Descriptor(#[from] DescriptorError),
+}
+
+/// Public keys trusted for each kind of descriptor
+#[derive(Debug, Clone)]
+pub struct CalKeys {
+    pub erc20: PublicKey,
+    pub external_plugin: PublicKey,
+    /// NFT metadata key, NFT descriptors are rejected without it
+    pub nft: Option<PublicKey>,
+    /// NFT selector key of SET PLUGIN, plugin descriptors are rejected
+    /// without it
+    pub plugin: Option<PublicKey>,
+}
+
+impl CalKeys {
+    /// Trust the CAL key of `environment` for the descriptors it signs: ERC 20
+    /// tokens and external plugins. NFT and plugin descriptors are signed
+    /// with their own keys, which have to be set for them to be verified:
+    /// until then they fail with [`VerificationError::MissingKey`].
+    pub fn new(environment: CalEnvironment) -> Self {
+        let key = environment.public_key();
+        CalKeys {
+            erc20: key,
+            external_plugin: key,
+            nft: None,
+            plugin: None,
+        }
+    }
+
+    /// Verify an ERC 20 token descriptor
+    pub fn verify_erc20(&self, info: &Erc20TokenInfo) -> Result<(), VerificationError> {
+        verify(
+            &self.erc20,
+            DescriptorKind::Erc20,
+            &info.signed_payload(),
+            &info.signature,
+        )
+    }
+
+    /// Verify an NFT descriptor
+    pub fn verify_nft(&self, info: &NftInfo) -> Result<(), VerificationError> {
+        let key = self
+            .nft
+            .as_ref()
+            .ok_or(VerificationError::MissingKey(DescriptorKind::Nft))?;
+        verify(
+            key,
+            DescriptorKind::Nft,
+            &info.signed_payload()?,
+            &info.signature,
+        )
+    }
+
+    /// Verify an external plugin descriptor
+    pub fn verify_external_plugin(
+        &self,
+        data: &ExternalPluginData,
+    ) -> Result<(), VerificationError> {
+        let payload = hex::decode(&data.payload).map_err(DescriptorError::from)?;
+        let signature = hex::decode(&data.signature).map_err(DescriptorError::from)?;
+        verify(
+            &self.external_plugin,
+            DescriptorKind::ExternalPlugin,
+            &payload,
+            &signature,
+        )
+    }
+
+    /// Verify an internal plugin descriptor
+    pub fn verify_plugin(&self, info: &PluginInfo) -> Result<(), VerificationError> {
+        let key = self
+            .plugin
+            .as_ref()
+            .ok_or(VerificationError::MissingKey(DescriptorKind::Plugin))?;
+        verify(
+            key,
+            DescriptorKind::Plugin,
+            &info.signed_payload()?,
+            &info.signature,
+        )
+    }
+}
+
+fn verify(
+    key: &PublicKey,
+    kind: DescriptorKind,
+    signed: &[u8],
+    der_signature: &[u8],
+) -> Result<(), VerificationError> {
+    let hash: [u8; 32] = Sha256::digest(signed).into();
+    // safe, a sha256 digest is 32 bytes
+    let message = Message::from_slice(&hash).unwrap();
+    let mut signature = Signature::from_der(der_signature)
+        .map_err(|_| VerificationError::MalformedSignature(kind))?;
+    // the device accepts high-s signatures, libsecp256k1 does not
+    signature.normalize_s();
+    SECP256K1
+        .verify_ecdsa(&message, &signature, key)
+        .map_err(|_| VerificationError::InvalidSignature(kind))
+}
+
+#[cfg(test)]
+mod tests {
+    use secp256k1::SecretKey;
+
+    use super::*;
+
+    fn sign(secret_key: &SecretKey, payload: &[u8]) -> Vec<u8> {
+        let hash: [u8; 32] = Sha256::digest(payload).into();
+        let message = Message::from_slice(&hash).unwrap();
+        SECP256K1
+            .sign_ecdsa(&message, secret_key)
+            .serialize_der()
+            .to_vec()
+    }
+
+    /// CAL keys of the production environment, with every descriptor kind
+    /// signed by a local test key
+    fn test_keys() -> (SecretKey, CalKeys) {
+        let secret_key = SecretKey::from_slice(&[0x11; 32]).unwrap();
+        let public_key = secret_key.public_key(SECP256K1);
+        let keys = CalKeys {
+            external_plugin: public_key,
+            nft: Some(public_key),
+            plugin: Some(public_key),
+            ..CalKeys::new(CalEnvironment::Production)
+        };
+        (secret_key, keys)
+    }
+
+    fn assert_invalid(result: Result<(), VerificationError>, kind: DescriptorKind) {
+        assert!(
+            matches!(result, Err(VerificationError::InvalidSignature(k)) if k == kind),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn verifies_erc20() {
+        // https://github.com/LedgerHQ/ledger-live/blob/develop/libs/ledgerjs/packages/cryptoassets/src/data/evm/5/erc20.json
+        let mut info = Erc20TokenInfo {
+            ticker: "USDC".into(),
+            address: hex::decode("07865c6E87B9F70255377e024ace6630C1Eaa37F")
+                .unwrap()
+                .try_into()
+                .unwrap(),
+            decimals: 6,
+            chain_id: 5,
+            signature: hex::decode("3045022100fde9f713cb999780a504b8eda31fe0195930935d8b0ad836e183b5c56b5e342d02202c15a0d1ad00b0dacae588524cf4db145934e10c8ba0e89da366e1793f723f70").unwrap(),
+        };
+        let keys = CalKeys::new(CalEnvironment::Production);
+        keys.verify_erc20(&info).unwrap();
+
+        info.decimals = 18;
+        assert_invalid(keys.verify_erc20(&info), DescriptorKind::Erc20);
+    }
+
+    #[test]
+    fn verifies_nft() {
+        let (secret_key, keys) = test_keys();
+        let mut info = NftInfo {
+            collection_name: "BoredApeYachtClub".into(),
+            contract_address: [0xbc; 20],
+            chain_id: 1,
+            key_id: 1,
+            algorithm_id: 1,
+            signature: vec![],
+        };
+        info.signature = sign(&secret_key, &info.signed_payload().unwrap());
+        keys.verify_nft(&info).unwrap();
+
+        info.contract_address[0] ^= 0x01;
+        assert_invalid(keys.verify_nft(&info), DescriptorKind::Nft);
+        // without a dedicated key, NFT descriptors are rejected
+        assert!(matches!(
+            CalKeys::new(CalEnvironment::Production).verify_nft(&info),
+            Err(VerificationError::MissingKey(DescriptorKind::Nft))
+        ));
+    }
+
+    #[test]
+    fn verifies_external_plugin() {
+        let (secret_key, keys) = test_keys();
+        let unsigned =
+            ExternalPluginData::new("Paraswap", [0xde; 20], [0xa9, 0x05, 0x9c, 0xbb], &[]).unwrap();
+        let signature = sign(&secret_key, &hex::decode(&unsigned.payload).unwrap());
+        let mut data = ExternalPluginData {
+            signature: hex::encode(signature),
+            ..unsigned
+        };
+        keys.verify_external_plugin(&data).unwrap();
+
+        data.payload.replace_range(..2, "09");
+        assert_invalid(
+            keys.verify_external_plugin(&data),
+            DescriptorKind::ExternalPlugin,
+        );
+    }
+
+    #[test]
+    fn verifies_plugin() {
+        let (secret_key, keys) = test_keys();
+        let mut info = PluginInfo {
+            plugin_type: 0x01,
+            version: 0x01,
+            plugin_name: "ERC721".into(),
+            contract_address: [0xbc; 20],
+            selector: [0x42, 0x84, 0x2e, 0x0e],
+            chain_id: 1,
+            key_id: 1,
+            algorithm_id: 1,
+            signature: vec![],
+        };
+        info.signature = sign(&secret_key, &info.signed_payload().unwrap());
+        keys.verify_plugin(&info).unwrap();
+
+        info.selector[3] ^= 0x01;
+        assert_invalid(keys.verify_plugin(&info), DescriptorKind::Plugin);
+        assert!(matches!(
+            CalKeys::new(CalEnvironment::Production).verify_plugin(&info),
+            Err(VerificationError::MissingKey(DescriptorKind::Plugin))
+        ));
+    }
+}
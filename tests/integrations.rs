@@ -1,14 +1,25 @@
 #![feature(once_cell)]
-use std::sync::LazyLock;
+use std::ops::Deref;
+use std::sync::{LazyLock, Mutex};
 
 use anyhow::Result;
-use byteorder::{BigEndian, WriteBytesExt};
-use ledger_ethereum::{Address, BIP44Path, EthApp, Signature};
+use ledger_ethereum::bip32::ExtendedPublicKey;
+use ledger_ethereum::eip712::TypedData;
+use ledger_ethereum::resolver::{AssetDatabase, ResolverError};
+use ledger_ethereum::transaction::{self, Eip1559Tx, LegacyTx, SignedTransaction, TypedTx};
+use ledger_ethereum::verification::{CalEnvironment, CalKeys};
+use ledger_ethereum::{
+    Address, BIP44Path, DerivationPath, DescriptorError, Erc20TokenInfo, Eth2Path, Eth2PublicKey,
+    EthAddress, EthApp, EthError, ExternalPluginData, LedgerEthTransactionResolution, NftInfo,
+    PathScheme, PluginInfo, Signature, VEncoding, VerificationOutcome,
+};
+use ledger_transport::{async_trait, APDUAnswer, APDUCommand, Exchange};
 use ledger_transport_speculos::api::{Button, Event};
 use ledger_transport_speculos::TransportSpeculosHttp;
 use secp256k1::hashes::sha256::Hash;
 use secp256k1::{Message, PublicKey};
 use serial_test::serial;
+use sha2::{Digest, Sha256};
 use tiny_keccak::{Hasher, Keccak};
 use tokio::spawn;
 
@@ -33,6 +44,60 @@ fn api_client() -> TransportSpeculosHttp {
     TransportSpeculosHttp::new("127.0.0.1", 5000)
 }
 
+/// Offline transport recording every command, and answering them with the
+/// first queued answer for their instruction, or with a bare `9000`
+#[derive(Default)]
+struct MockTransport {
+    answers: Mutex<Vec<(u8, Vec<u8>)>>,
+    commands: Mutex<Vec<APDUCommand<Vec<u8>>>>,
+}
+
+impl MockTransport {
+    fn with_answers(answers: impl IntoIterator<Item = (u8, Vec<u8>)>) -> Self {
+        MockTransport {
+            answers: Mutex::new(answers.into_iter().collect()),
+            ..Default::default()
+        }
+    }
+
+    fn instructions(&self) -> Vec<u8> {
+        self.commands
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|c| c.ins)
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Exchange for &MockTransport {
+    type Error = std::io::Error;
+    type AnswerType = Vec<u8>;
+
+    async fn exchange<I>(
+        &self,
+        command: &APDUCommand<I>,
+    ) -> Result<APDUAnswer<Self::AnswerType>, Self::Error>
+    where
+        I: Deref<Target = [u8]> + Send + Sync,
+    {
+        self.commands.lock().unwrap().push(APDUCommand {
+            cla: command.cla,
+            ins: command.ins,
+            p1: command.p1,
+            p2: command.p2,
+            data: command.data.to_vec(),
+        });
+        let mut answers = self.answers.lock().unwrap();
+        let answer = match answers.iter().position(|(ins, _)| *ins == command.ins) {
+            Some(i) => answers.remove(i).1,
+            None => vec![0x90, 0x00],
+        };
+        Ok(APDUAnswer::from_answer(answer).unwrap())
+    }
+}
+
 // 44'/60'/0'/0'/0
 // seed: 6f0cd08f62d99e62ebb1e15f46df842c02380fd9f2abf987f0b5463adae25caeb564583bd413c9b7cbf0391808308332251e47696dd13688dc96b9edbccd981b
 fn first_address() -> BIP44Path {
@@ -54,9 +119,66 @@ async fn can_get_address() -> Result<()> {
         address,
         ..
     } = app.address(&first_address(), None, None).await?;
-    let address = "0x".to_string() + &String::from_utf8(address)?;
     assert_eq!(EXPECTED_PUBKEY.as_slice(), public_key);
-    assert_eq!("0x7562EF289fAf3554eEd27844B6473f165887cd40", address);
+    assert_eq!(
+        "0x7562EF289fAf3554eEd27844B6473f165887cd40",
+        address.to_string()
+    );
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn can_reject_address_verification() -> Result<()> {
+    let app = app();
+    let client = api_client();
+    let expected = "0x7562EF289fAf3554eEd27844B6473f165887cd40".parse()?;
+    let handle = spawn(async move { app.verify_address(&first_address(), expected).await });
+    // the flow loops, so the reject screen is left of the first one
+    client.button(Button::Left).await?;
+    client.button(Button::Both).await?;
+    assert_eq!(VerificationOutcome::Rejected, handle.await??);
+    Ok(())
+}
+
+#[tokio::test]
+async fn can_verify_address_before_display() -> Result<()> {
+    let mut answer = vec![65];
+    answer.extend_from_slice(&EXPECTED_PUBKEY);
+    answer.push(40);
+    answer.extend_from_slice(b"7562ef289faf3554eed27844b6473f165887cd40");
+    answer.extend_from_slice(&[0x90, 0x00]);
+    let displays = |transport: &MockTransport| -> Vec<u8> {
+        let commands = transport.commands.lock().unwrap();
+        commands.iter().map(|c| c.p1).collect()
+    };
+
+    // a wrong address is never displayed
+    let transport = MockTransport::with_answers([(0x02, answer.clone())]);
+    let outcome = EthApp::new(&transport)
+        .verify_address(first_address(), EthAddress([0x35; 20]))
+        .await?;
+    assert_eq!(
+        VerificationOutcome::Mismatch {
+            device: "0x7562EF289fAf3554eEd27844B6473f165887cd40".parse()?
+        },
+        outcome
+    );
+    assert_eq!(vec![0], displays(&transport));
+
+    let expected = "0x7562EF289fAf3554eEd27844B6473f165887cd40".parse()?;
+    let transport = MockTransport::with_answers([(0x02, answer.clone()), (0x02, answer.clone())]);
+    let outcome = EthApp::new(&transport)
+        .verify_address(first_address(), expected)
+        .await?;
+    assert_eq!(VerificationOutcome::Confirmed, outcome);
+    assert_eq!(vec![0, 1], displays(&transport));
+
+    let transport = MockTransport::with_answers([(0x02, answer), (0x02, vec![0x69, 0x85])]);
+    let outcome = EthApp::new(&transport)
+        .verify_address(first_address(), expected)
+        .await?;
+    assert_eq!(VerificationOutcome::Rejected, outcome);
     Ok(())
 }
 
@@ -93,6 +215,519 @@ async fn can_sign_transaction() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+#[serial]
+async fn can_sign_personal_message() -> Result<()> {
+    let app = app();
+    let path = first_address();
+    let message = b"hello ledger";
+    let client = api_client();
+    let handle = spawn(async move { app.sign_personal_message(&path, message).await });
+    for _ in 0..3 {
+        client.button(Button::Right).await?;
+    }
+    client.button(Button::Both).await?;
+    let Signature { r, s, .. } = handle.await??;
+    let sig = secp256k1::ecdsa::Signature::from_compact([r, s].concat().as_slice())?;
+    let pubkey = PublicKey::from_slice(&EXPECTED_PUBKEY)?;
+    let mut prefixed = format!("\x19Ethereum Signed Message:\n{}", message.len()).into_bytes();
+    prefixed.extend_from_slice(message);
+    let msg = Message::from_slice(&keccak256_hash(&prefixed))?;
+    sig.verify(&msg, &pubkey)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn can_sign_eip712_hashed() -> Result<()> {
+    let app = app();
+    let path = first_address();
+    let domain_separator: [u8; 32] =
+        hex::decode("f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f")?
+            .try_into()
+            .unwrap();
+    let message_hash: [u8; 32] =
+        hex::decode("c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e")?
+            .try_into()
+            .unwrap();
+    let client = api_client();
+    let handle = spawn(async move {
+        app.sign_eip712_hashed(&path, domain_separator, message_hash)
+            .await
+    });
+    for _ in 0..5 {
+        client.button(Button::Right).await?;
+    }
+    client.button(Button::Both).await?;
+    let Signature { r, s, .. } = handle.await??;
+    let sig = secp256k1::ecdsa::Signature::from_compact([r, s].concat().as_slice())?;
+    let pubkey = PublicKey::from_slice(&EXPECTED_PUBKEY)?;
+    let msg = Message::from_slice(&keccak256_hash(
+        &[&[0x19, 0x01], &domain_separator[..], &message_hash[..]].concat(),
+    ))?;
+    sig.verify(&msg, &pubkey)?;
+    Ok(())
+}
+
+const MAIL_TYPED_DATA: &str = r#"{
+    "types": {
+        "EIP712Domain": [
+            { "name": "name", "type": "string" },
+            { "name": "version", "type": "string" },
+            { "name": "chainId", "type": "uint256" },
+            { "name": "verifyingContract", "type": "address" }
+        ],
+        "Person": [
+            { "name": "name", "type": "string" },
+            { "name": "wallet", "type": "address" }
+        ],
+        "Mail": [
+            { "name": "from", "type": "Person" },
+            { "name": "to", "type": "Person" },
+            { "name": "contents", "type": "string" }
+        ]
+    },
+    "primaryType": "Mail",
+    "domain": {
+        "name": "Ether Mail",
+        "version": "1",
+        "chainId": 5,
+        "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+    },
+    "message": {
+        "from": { "name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826" },
+        "to": { "name": "Bob", "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB" },
+        "contents": "Hello, Bob!"
+    }
+}"#;
+
+#[tokio::test]
+#[serial]
+async fn can_sign_eip712() -> Result<()> {
+    let app = app();
+    let path = first_address();
+    let typed_data: TypedData = MAIL_TYPED_DATA.parse()?;
+    let client = api_client();
+    let handle = spawn(async move { app.sign_eip712(&path, &typed_data, None).await });
+    for _ in 0..10 {
+        client.button(Button::Right).await?;
+    }
+    client.button(Button::Both).await?;
+    let Signature { r, s, .. } = handle.await??;
+    let sig = secp256k1::ecdsa::Signature::from_compact([r, s].concat().as_slice())?;
+    let pubkey = PublicKey::from_slice(&EXPECTED_PUBKEY)?;
+    // hashStruct(EIP712Domain) and hashStruct(Mail) for chain id 5
+    let domain_separator =
+        hex::decode("6137beb405d9ff777172aa879e33edb34a1460e701802746c5ef96e741710e59")?;
+    let message_hash =
+        hex::decode("c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e")?;
+    let msg = Message::from_slice(&keccak256_hash(
+        &[&[0x19, 0x01], &domain_separator[..], &message_hash[..]].concat(),
+    ))?;
+    sig.verify(&msg, &pubkey)?;
+    Ok(())
+}
+
+#[test]
+fn can_hash_eip712_typed_data() -> Result<()> {
+    // https://github.com/ethereum/EIPs/blob/master/assets/eip-712/Example.js
+    let typed_data: TypedData = MAIL_TYPED_DATA
+        .replace("\"chainId\": 5", "\"chainId\": 1")
+        .parse()?;
+    assert_eq!(
+        "Mail(Person from,Person to,string contents)Person(string name,address wallet)",
+        typed_data.encode_type("Mail")?
+    );
+    assert_eq!(
+        "f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f",
+        hex::encode(typed_data.domain_separator()?)
+    );
+    assert_eq!(
+        "c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e",
+        hex::encode(typed_data.hash_struct("Mail", &typed_data.message)?)
+    );
+    assert_eq!(
+        "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2",
+        hex::encode(typed_data.signing_hash()?)
+    );
+
+    let goerli: TypedData = MAIL_TYPED_DATA.parse()?;
+    assert_eq!(
+        "6137beb405d9ff777172aa879e33edb34a1460e701802746c5ef96e741710e59",
+        hex::encode(goerli.domain_separator()?)
+    );
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg(feature = "recovery")]
+async fn can_verify_eip712_signature() -> Result<()> {
+    let typed_data: TypedData = MAIL_TYPED_DATA
+        .replace("\"chainId\": 5", "\"chainId\": 1")
+        .parse()?;
+    // signature of the example by `keccak256("cow")`
+    let signature = Signature {
+        v: 28,
+        r: hex::decode("4355c47d63924e8a72e509b65029052eb6c299d53a04e167c5775fd466751c9d")?
+            .try_into()
+            .unwrap(),
+        s: hex::decode("07299936d304c153f6443dfa05f40ff007d72911b6f72307f996231605b91562")?
+            .try_into()
+            .unwrap(),
+    };
+    let cow = signature.recover_public_key(&typed_data.signing_hash()?)?;
+    assert_eq!(
+        "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826",
+        EthAddress::from_public_key(&cow.serialize())?.to_string()
+    );
+
+    let sign = |signature: &Signature| -> Result<MockTransport> {
+        let mut address = vec![65];
+        address.extend_from_slice(&cow.serialize_uncompressed());
+        address.push(40);
+        address.extend_from_slice(b"cd2a3d9f938e13cd947ec05abc7fe734df8dd826");
+        address.extend_from_slice(&[0x90, 0x00]);
+        let mut answer = vec![signature.v as u8];
+        answer.extend_from_slice(&signature.r);
+        answer.extend_from_slice(&signature.s);
+        answer.extend_from_slice(&[0x90, 0x00]);
+        Ok(MockTransport::with_answers([
+            (0x0c, answer),
+            (0x02, address),
+        ]))
+    };
+
+    let transport = sign(&signature)?;
+    let app = EthApp::new(&transport).with_signature_verification();
+    assert_eq!(
+        signature,
+        app.sign_eip712(first_address(), &typed_data, None).await?
+    );
+
+    // a tampered response is caught
+    let tampered = Signature {
+        s: [0x11; 32],
+        ..signature
+    };
+    let transport = sign(&tampered)?;
+    let app = EthApp::new(&transport).with_signature_verification();
+    let error = app
+        .sign_eip712(first_address(), &typed_data, None)
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(
+            error,
+            EthError::Recovery(ledger_ethereum::recovery::RecoveryError::AddressMismatch { .. })
+        ),
+        "{error}"
+    );
+    Ok(())
+}
+
+#[test]
+fn can_parse_derivation_path() -> Result<()> {
+    let path: DerivationPath = "m/44'/60'/0'/0/0".parse()?;
+    assert_eq!(path, "44h/60H/0'/0/0".parse()?);
+    assert_eq!("m/44'/60'/0'/0/0", path.to_string());
+    assert_eq!(
+        "058000002c8000003c800000000000000000000000",
+        hex::encode(path.serialize())
+    );
+
+    // legacy MEW/MyCrypto path
+    let legacy: DerivationPath = "m/44'/60'/0'/3".parse()?;
+    assert_eq!(
+        &[0x8000_002c, 0x8000_003c, 0x8000_0000, 3],
+        legacy.components()
+    );
+
+    assert!("m/44'/60'/x".parse::<DerivationPath>().is_err());
+    assert!("m/2147483648".parse::<DerivationPath>().is_err());
+    assert!("m/0/0/0/0/0/0/0/0/0/0/0".parse::<DerivationPath>().is_err());
+    assert_eq!(
+        path,
+        DerivationPath::from("44'/60'/0'/0/0".parse::<BIP44Path>()?)
+    );
+
+    let eth2 = DerivationPath::from(&Eth2Path::signing(3));
+    assert_eq!("m/12381/3600/3/0/0", eth2.to_string());
+    assert_eq!(Eth2Path::signing(3).serialize_eth2(), eth2.serialize());
+    assert!(Eth2Path::new(vec![]).is_err());
+    assert!(Eth2Path::new(vec![0; 11]).is_err());
+    assert_eq!(
+        Eth2Path::withdrawal(3),
+        Eth2Path::new(vec![12381, 3600, 3, 0])?
+    );
+    Ok(())
+}
+
+#[test]
+fn can_derive_scheme_paths() -> Result<()> {
+    assert_eq!(
+        "m/44'/60'/3'/0/0",
+        PathScheme::LedgerLive.path(3)?.to_string()
+    );
+    assert_eq!("m/44'/60'/0'/3", PathScheme::Legacy.path(3)?.to_string());
+    assert_eq!("m/44'/60'/0'/0/3", PathScheme::Bip44.path(3)?.to_string());
+    let custom = PathScheme::Custom("m/44'/60'/x'".into());
+    assert_eq!("m/44'/60'/3'", custom.path(3)?.to_string());
+    assert!(PathScheme::Custom("m/44'/60'".into()).path(3).is_err());
+    Ok(())
+}
+
+#[test]
+fn can_derive_extended_public_key() -> Result<()> {
+    // https://github.com/bitcoin/bips/blob/master/bip-0032.mediawiki#test-vector-2
+    let master = ExtendedPublicKey::new(
+        PublicKey::from_slice(&hex::decode(
+            "03cbcaa9c98c877a26977d00825c956a238e8dddfbd322cce4f74b0b5bd6ace4a7",
+        )?)?,
+        hex::decode("60499f801b896d83179a4374aeb7822aaeaceaa0db1f85ee3e904c4defbd9689")?
+            .try_into()
+            .unwrap(),
+    );
+    assert_eq!(
+        "xpub661MyMwAqRbcFW31YEwpkMuc5THy2PSt5bDMsktWQcFF8syAmRUapSCGu8ED9W6oDMSgv6Zz8idoc4a6mr8BDzTJY47LJhkJ8UB7WEGuduB",
+        master.to_xpub()
+    );
+    assert_eq!(
+        "xpub69H7F5d8KSRgmmdJg2KhpAK8SR3DjMwAdkxj3ZuxV27CprR9LgpeyGmXUbC6wb7ERfvrnKZjXoUmmDznezpbZb7ap6r1D3tgFxHmwMkQTPH",
+        master.derive_child(0)?.to_xpub()
+    );
+    assert!(master.derive_child(0x8000_0000).is_err());
+    Ok(())
+}
+
+#[test]
+fn can_checksum_address() -> Result<()> {
+    // https://eips.ethereum.org/EIPS/eip-55#test-cases
+    let address: EthAddress = "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed".parse()?;
+    assert_eq!(
+        "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+        address.to_string()
+    );
+    assert_eq!(
+        address,
+        "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed".parse()?
+    );
+    assert!("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD"
+        .parse::<EthAddress>()
+        .is_err());
+    assert!("0x0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed"
+        .parse::<EthAddress>()
+        .is_err());
+
+    assert_eq!(
+        "0x7562EF289fAf3554eEd27844B6473f165887cd40",
+        EthAddress::from_public_key(&EXPECTED_PUBKEY)?.to_string()
+    );
+    assert_eq!(
+        "\"0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed\"",
+        serde_json::to_string(&address)?
+    );
+    Ok(())
+}
+
+#[test]
+fn can_encode_legacy_tx() -> Result<()> {
+    let tx = TypedTx::from(LegacyTx {
+        chain_id: Some(5),
+        nonce: 0,
+        gas_price: 1_000_000,
+        gas_limit: 1_000_000,
+        to: Some(
+            hex::decode("7562ef289faf3554eed27844b6473f165887cd40")?
+                .try_into()
+                .unwrap(),
+        ),
+        value: 1_000_000_000_000,
+        data: vec![],
+    });
+    assert_eq!(
+        "e880830f4240830f4240947562ef289faf3554eed27844b6473f165887cd4085e8d4a5100080058080",
+        hex::encode(tx.encode_unsigned())
+    );
+    Ok(())
+}
+
+#[test]
+fn can_assemble_signed_legacy_tx() -> Result<()> {
+    // https://eips.ethereum.org/EIPS/eip-155#example
+    let tx = TypedTx::from(LegacyTx {
+        chain_id: Some(1),
+        nonce: 9,
+        gas_price: 20_000_000_000,
+        gas_limit: 21_000,
+        to: Some([0x35; 20]),
+        value: 1_000_000_000_000_000_000,
+        data: vec![],
+    });
+    let signature = Signature {
+        v: 37,
+        r: hex::decode("28ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276")?
+            .try_into()
+            .unwrap(),
+        s: hex::decode("67cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83")?
+            .try_into()
+            .unwrap(),
+    };
+    let signed = SignedTransaction::from_parts(tx, signature)?;
+    assert_eq!(37, signed.v);
+    assert_eq!(
+        "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83",
+        hex::encode(&signed.raw)
+    );
+    assert_eq!(
+        "33469b22e9f636356c4160a87eb19df52b7412e8eac32a4a55ffe88ea8350788",
+        hex::encode(signed.hash)
+    );
+    Ok(())
+}
+
+#[test]
+fn can_decode_eip1559_tx() -> Result<()> {
+    let tx: TypedTx = Eip1559Tx {
+        chain_id: 1,
+        nonce: 3,
+        max_priority_fee_per_gas: 1_000_000_000,
+        max_fee_per_gas: 30_000_000_000,
+        gas_limit: 60_000,
+        to: Some([0x35; 20]),
+        value: 1_500_000_000_000_000_000,
+        data: hex::decode("a9059cbb")?,
+        access_list: vec![],
+    }
+    .into();
+
+    let decoded = transaction::decode(&tx.encode_unsigned())?;
+    assert_eq!(decoded, tx);
+
+    let summary = decoded.summary();
+    assert_eq!(summary.value_ether, "1.5");
+    assert_eq!(summary.selector.as_deref(), Some("0xa9059cbb"));
+    assert_eq!(
+        decoded.to_string(),
+        "eip1559 transaction on chain 1, nonce 3, to 0x3535353535353535353535353535353535353535, \
+         value 1.5 ETH (1500000000000000000 wei), gas limit 60000, max fee 30000000000 wei, \
+         priority fee 1000000000 wei, 4 bytes of data (selector 0xa9059cbb)"
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "recovery")]
+fn can_recover_signer_of_legacy_tx() -> Result<()> {
+    // https://eips.ethereum.org/EIPS/eip-155#example
+    let tx = TypedTx::from(LegacyTx {
+        chain_id: Some(1),
+        nonce: 9,
+        gas_price: 20_000_000_000,
+        gas_limit: 21_000,
+        to: Some([0x35; 20]),
+        value: 1_000_000_000_000_000_000,
+        data: vec![],
+    });
+    let hash = tx.signing_hash();
+    assert_eq!(
+        "daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53",
+        hex::encode(hash)
+    );
+    let signature = Signature {
+        v: 37,
+        r: hex::decode("28ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276")?
+            .try_into()
+            .unwrap(),
+        s: hex::decode("67cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83")?
+            .try_into()
+            .unwrap(),
+    };
+    assert_eq!(
+        "9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f",
+        hex::encode(signature.recover_address(&hash)?.0)
+    );
+    let public_key = signature.recover_public_key(&hash)?;
+    signature.verify(&public_key.serialize(), &hash)?;
+    Ok(())
+}
+
+#[test]
+fn can_encode_signature() -> Result<()> {
+    // https://eips.ethereum.org/EIPS/eip-2098#test-cases
+    let signature = Signature {
+        v: 28,
+        r: hex::decode("9328da16089fcba9bececa81663203989f2df5fe1faa6291a45381c81bd17f76")?
+            .try_into()
+            .unwrap(),
+        s: hex::decode("139c6d6b623b42da56557e5e734a43dc83345ddfadec52cbe24d0cc64f550793")?
+            .try_into()
+            .unwrap(),
+    };
+    let compact = signature.to_eip2098()?;
+    assert_eq!(
+        "939c6d6b623b42da56557e5e734a43dc83345ddfadec52cbe24d0cc64f550793",
+        hex::encode(&compact[32..])
+    );
+    assert_eq!(1, Signature::from_eip2098(&compact).v);
+
+    let rsv = signature.to_hex(VEncoding::Parity)?;
+    assert!(rsv.ends_with("01"));
+    assert_eq!(
+        signature,
+        Signature::from_hex(&signature.to_hex(VEncoding::Electrum)?)?
+    );
+    assert!(Signature::from_hex(&format!("0x{rsv}")).is_err());
+
+    let json = serde_json::to_string(&signature)?;
+    assert_eq!(signature, serde_json::from_str(&json)?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn can_recover_v_of_large_chain_id() -> Result<()> {
+    // polygon: 137 * 2 + 35 + 1 = 310, of which the device only returns 54
+    let tx = TypedTx::from(LegacyTx {
+        chain_id: Some(137),
+        ..Default::default()
+    });
+    let signature = Signature {
+        v: 54,
+        r: [1; 32],
+        s: [1; 32],
+    };
+    assert_eq!(310, SignedTransaction::from_parts(tx, signature)?.v);
+
+    // arbitrum: 42161 * 2 + 35 + 0 = 84357, of which the device only returns 0x85
+    let tx = TypedTx::from(LegacyTx {
+        chain_id: Some(42161),
+        ..Default::default()
+    });
+    let mut answer = vec![0x85];
+    answer.extend_from_slice(&[1; 64]);
+    answer.extend_from_slice(&[0x90, 0x00]);
+    let transport = MockTransport::with_answers([(0x04, answer)]);
+    let signature = EthApp::new(&transport)
+        .sign_tx(first_address(), &tx)
+        .await?;
+    assert_eq!(84357, signature.v);
+
+    // the EIP-155 v of this chain id does not fit in 64 bits
+    let tx = TypedTx::from(LegacyTx {
+        chain_id: Some(u64::MAX / 2),
+        ..Default::default()
+    });
+    let signature = Signature {
+        v: 33,
+        r: [1; 32],
+        s: [1; 32],
+    };
+    assert!(matches!(
+        SignedTransaction::from_parts(tx, signature),
+        Err(transaction::TransactionError::ChainIdOverflow(_))
+    ));
+    Ok(())
+}
+
 fn keccak256_hash(bytes: &[u8]) -> [u8; 32] {
     let mut hasher = Keccak::v256();
     hasher.update(bytes);
@@ -109,6 +744,134 @@ async fn can_get_app_configuration() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+#[serial]
+async fn can_get_eth2_public_key() -> Result<()> {
+    // EIP-2333 key of the speculos seed
+    let Eth2PublicKey { public_key } = app().eth2_public_key(&Eth2Path::signing(0), false).await?;
+    assert_eq!(
+        "991808b3dff5ae01a457fe38be87071801b6634d649b0641ea8366013d2df0bfeab2489eb11349d235d19561b1593ef8",
+        hex::encode(public_key)
+    );
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn can_set_eth2_withdrawal_index() -> Result<()> {
+    let app = app();
+    app.set_eth2_withdrawal_index(1).await?;
+    let withdrawal_key = app.eth2_public_key(&Eth2Path::withdrawal(1), false).await?;
+    let mut expected: [u8; 32] = Sha256::digest(withdrawal_key.public_key).into();
+    expected[0] = Eth2PublicKey::BLS_WITHDRAWAL_PREFIX;
+    assert_eq!(expected, withdrawal_key.withdrawal_credentials());
+    Ok(())
+}
+
+#[test]
+fn can_derive_withdrawal_credentials() -> Result<()> {
+    let key = Eth2PublicKey {
+        public_key: hex::decode("a99a76ed7796f7be22d5b7e85deeb7c5677e88e511e0b337618f8c4eb61349b4bf2d153f649f7b53359fe8b94a38e44c")?
+            .try_into()
+            .unwrap(),
+    };
+    assert_eq!(
+        "00fad2a6bfb0e7f1f0f45460944fbd8dfa7f37da06a4d13b3983cc90bb46963b",
+        hex::encode(key.withdrawal_credentials())
+    );
+    Ok(())
+}
+
+#[test]
+fn can_encode_nft_info() -> Result<()> {
+    let info = NftInfo {
+        collection_name: "BoredApeYachtClub".into(),
+        contract_address: hex::decode("bc4ca0eda7647a8ab7c2061c2e118a18a936f13d")?
+            .try_into()
+            .unwrap(),
+        chain_id: 1,
+        key_id: 1,
+        algorithm_id: 1,
+        signature: vec![0xaa; 4],
+    };
+    let payload = info.serialize()?;
+    assert_eq!(
+        "010111426f7265644170655961636874436c7562bc4ca0eda7647a8ab7c2061c2e118a18a936f13d0000000000000001010104aaaaaaaa",
+        hex::encode(&payload)
+    );
+    assert_eq!(info, NftInfo::parse(&payload)?);
+
+    let mut invalid = payload;
+    invalid[3] = 0xff;
+    assert!(matches!(
+        NftInfo::parse(&invalid),
+        Err(DescriptorError::InvalidUtf8("collection name"))
+    ));
+    Ok(())
+}
+
+#[test]
+fn can_encode_external_plugin_data() -> Result<()> {
+    let data = ExternalPluginData::new(
+        "Paraswap",
+        hex::decode("def171fe48cf0115b1d80b88dc8eab59176fee57")?
+            .try_into()
+            .unwrap(),
+        [0x54, 0xe3, 0xf3, 0x1b],
+        &[0x30, 0x01, 0xaa],
+    )?;
+    // name length || name || contract address || selector || signature
+    assert_eq!(
+        "085061726173776170def171fe48cf0115b1d80b88dc8eab59176fee5754e3f31b3001aa",
+        hex::encode(data.serialize()?)
+    );
+    Ok(())
+}
+
+#[test]
+fn can_encode_plugin_info() -> Result<()> {
+    let info = PluginInfo {
+        plugin_type: 0x01,
+        version: 0x01,
+        plugin_name: "ERC721".into(),
+        contract_address: hex::decode("bc4ca0eda7647a8ab7c2061c2e118a18a936f13d")?
+            .try_into()
+            .unwrap(),
+        selector: [0x42, 0x84, 0x2e, 0x0e],
+        chain_id: 1,
+        key_id: 1,
+        algorithm_id: 1,
+        signature: vec![0xaa; 4],
+    };
+    let payload = info.serialize()?;
+    assert_eq!(
+        "010106455243373231bc4ca0eda7647a8ab7c2061c2e118a18a936f13d42842e0e0000000000000001010104aaaaaaaa",
+        hex::encode(&payload)
+    );
+    assert_eq!(info, PluginInfo::parse(&payload)?);
+
+    let mut invalid = payload.clone();
+    invalid[3] = 0xff;
+    assert!(matches!(
+        PluginInfo::parse(&invalid),
+        Err(DescriptorError::InvalidUtf8("plugin name"))
+    ));
+
+    let mut invalid = payload.clone();
+    invalid[0] = 0x02;
+    assert!(matches!(
+        PluginInfo::parse(&invalid),
+        Err(DescriptorError::Unsupported("type", 0x02))
+    ));
+    let mut invalid = payload;
+    invalid[1] = 0x02;
+    assert!(matches!(
+        PluginInfo::parse(&invalid),
+        Err(DescriptorError::Unsupported("version", 0x02))
+    ));
+    Ok(())
+}
+
 #[tokio::test]
 #[serial]
 async fn can_provide_erc20_info() -> Result<()> {
@@ -131,34 +894,271 @@ async fn can_provide_erc20_info() -> Result<()> {
     dbg!(hex::encode(message.as_ref()));
     sig.verify(&message, &ledger_pubkey)?;
     // https://github.com/LedgerHQ/ledger-live/blob/develop/libs/ledgerjs/packages/cryptoassets/src/data/evm/5/erc20.json
-    let mut payload = vec![];
-    payload.write_u8(4)?;
-    payload.extend_from_slice(b"USDC");
-    payload.extend_from_slice(&hex::decode("07865c6E87B9F70255377e024ace6630C1Eaa37F")?);
-    payload.write_u32::<BigEndian>(6)?;
-    payload.write_u32::<BigEndian>(5)?;
-    payload.extend_from_slice(&sig1);
+    let info = Erc20TokenInfo {
+        ticker: "USDC".into(),
+        address: hex::decode("07865c6E87B9F70255377e024ace6630C1Eaa37F")?
+            .try_into()
+            .unwrap(),
+        decimals: 6,
+        chain_id: 5,
+        signature: sig1,
+    };
+    let payload = info.serialize()?;
     dbg!(hex::encode(&payload));
+    assert_eq!(info, Erc20TokenInfo::parse(&payload)?);
+    CalKeys::new(CalEnvironment::Production).verify_erc20(&info)?;
     app().provide_erc20_token_info(&payload).await?;
+    app().provide_erc20(&info).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn can_provide_erc20_with_or_without_token_index() -> Result<()> {
+    let info = Erc20TokenInfo {
+        ticker: "USDC".into(),
+        address: [0x07; 20],
+        decimals: 6,
+        chain_id: 5,
+        signature: vec![0xaa; 4],
+    };
+
+    let transport = MockTransport::with_answers([(0x0a, vec![0x01, 0x90, 0x00])]);
+    assert_eq!(Some(1), EthApp::new(&transport).provide_erc20(&info).await?);
+
+    // older app versions answer without the token index
+    let transport = MockTransport::default();
+    assert_eq!(None, EthApp::new(&transport).provide_erc20(&info).await?);
+    Ok(())
+}
+
+#[test]
+fn can_reject_invalid_erc20_ticker() -> Result<()> {
+    // USDC on goerli, with the ticker bytes replaced by an invalid UTF-8 sequence
+    let payload = hex::decode("04ff53444307865c6e87b9f70255377e024ace6630c1eaa37f00000006000000053045022100fde9f713cb999780a504b8eda31fe0195930935d8b0ad836e183b5c56b5e342d02202c15a0d1ad00b0dacae588524cf4db145934e10c8ba0e89da366e1793f723f70")?;
+    assert!(matches!(
+        Erc20TokenInfo::parse(&payload),
+        Err(DescriptorError::InvalidUtf8("ticker"))
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn can_reject_resolution_over_token_slots() -> Result<()> {
+    let transport = MockTransport::default();
+    let app = EthApp::new(&transport);
+    let usdc = "045553444307865c6e87b9f70255377e024ace6630c1eaa37f00000006000000053045022100fde9f713cb999780a504b8eda31fe0195930935d8b0ad836e183b5c56b5e342d02202c15a0d1ad00b0dacae588524cf4db145934e10c8ba0e89da366e1793f723f70";
+    let resolution = LedgerEthTransactionResolution {
+        erc20_tokens: vec![usdc.into(); 3],
+        ..Default::default()
+    };
+    let error = app.provide_resolution(&resolution).await.unwrap_err();
+    assert!(
+        matches!(&error, EthError::ResolutionRejected { descriptor, .. } if descriptor == "ERC 20 token #2"),
+        "{error}"
+    );
+    assert!(transport.instructions().is_empty());
+
+    let resolution = LedgerEthTransactionResolution {
+        erc20_tokens: vec![usdc.into()],
+        nfts: vec!["00".into(); 2],
+        ..Default::default()
+    };
+    let error = app.provide_resolution(&resolution).await.unwrap_err();
+    assert!(
+        matches!(&error, EthError::ResolutionRejected { descriptor, .. } if descriptor == "NFT #1"),
+        "{error}"
+    );
+    assert!(transport.instructions().is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn can_summarize_rejected_transaction() -> Result<()> {
+    let raw_tx = TypedTx::from(LegacyTx {
+        chain_id: Some(5),
+        nonce: 7,
+        ..Default::default()
+    })
+    .encode_unsigned();
+
+    // conditions of use not satisfied: rejected on the device
+    let transport = MockTransport::with_answers([(0x04, vec![0x69, 0x85])]);
+    let error = EthApp::new(&transport)
+        .sign(first_address(), &raw_tx, None)
+        .await
+        .unwrap_err();
+    match error {
+        EthError::Signing { summary, .. } => assert_eq!(7, summary.nonce),
+        error => panic!("unexpected error: {error}"),
+    }
+
+    // descriptors are rejected before signing, and not wrapped
+    let transport = MockTransport::default();
+    let resolution = LedgerEthTransactionResolution {
+        nfts: vec!["00".into(); 3],
+        ..Default::default()
+    };
+    let error = EthApp::new(&transport)
+        .sign(first_address(), &raw_tx, Some(resolution))
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(error, EthError::ResolutionRejected { .. }),
+        "{error}"
+    );
+    assert!(transport.instructions().is_empty());
     Ok(())
 }
 
 #[tokio::test]
 #[ignore = "must build eth app without CHAIN=goerli"]
 async fn can_test_known_erc20() -> Result<()> {
-    let ticker = hex::decode("5a5258")?;
-    let addr = hex::decode("e41d2489571d322189246dafa5ebde1f4699f498")?;
-    let dec = 18;
-    let chain_id = 1;
-    let sig = hex::decode("304402200ae8634c22762a8ba41d2acb1e068dcce947337c6dd984f13b820d396176952302203306a49d8a6c35b11a61088e1570b3928ca3a0db6bd36f577b5ef87628561ff7")?;
-    let mut payload = vec![];
-    payload.write_u8(ticker.len() as u8)?;
-    payload.extend_from_slice(&ticker);
-    payload.extend_from_slice(&addr);
-    payload.write_u32::<BigEndian>(dec)?;
-    payload.write_u32::<BigEndian>(chain_id)?;
-    payload.extend_from_slice(&sig);
+    let info = Erc20TokenInfo {
+        ticker: "ZRX".into(),
+        address: hex::decode("e41d2489571d322189246dafa5ebde1f4699f498")?
+            .try_into()
+            .unwrap(),
+        decimals: 18,
+        chain_id: 1,
+        signature: hex::decode("304402200ae8634c22762a8ba41d2acb1e068dcce947337c6dd984f13b820d396176952302203306a49d8a6c35b11a61088e1570b3928ca3a0db6bd36f577b5ef87628561ff7")?,
+    };
 
-    app().provide_erc20_token_info(&payload).await?;
+    app().provide_erc20(&info).await?;
+    Ok(())
+}
+
+#[test]
+fn can_resolve_erc20_transfer() -> Result<()> {
+    let db = AssetDatabase::from_json(
+        r#"{
+            "tokens": [{
+                "chain_id": 5,
+                "contract_address": "0x07865c6E87B9F70255377e024ace6630C1Eaa37F",
+                "payload": "045553444307865c6e87b9f70255377e024ace6630c1eaa37f00000006000000053045022100fde9f713cb999780a504b8eda31fe0195930935d8b0ad836e183b5c56b5e342d02202c15a0d1ad00b0dacae588524cf4db145934e10c8ba0e89da366e1793f723f70"
+            }]
+        }"#,
+    )?;
+    // USDC transfer(0x7562EF289fAf3554eEd27844B6473f165887cd40, 1000000) on goerli
+    let raw_tx = hex::decode("f86880830f4240830f42409407865c6e87b9f70255377e024ace6630c1eaa37f80b844a9059cbb0000000000000000000000007562ef289faf3554eed27844b6473f165887cd4000000000000000000000000000000000000000000000000000000000000f4240058080")?;
+    let resolution = db.resolve(&raw_tx)?;
+    assert_eq!(vec!["045553444307865c6e87b9f70255377e024ace6630c1eaa37f00000006000000053045022100fde9f713cb999780a504b8eda31fe0195930935d8b0ad836e183b5c56b5e342d02202c15a0d1ad00b0dacae588524cf4db145934e10c8ba0e89da366e1793f723f70".to_string()], resolution.erc20_tokens);
+    assert!(resolution.nfts.is_empty());
+    Ok(())
+}
+
+#[test]
+fn can_resolve_with_cal_erc20_signatures() -> Result<()> {
+    // USDC record of the goerli erc20-signatures file
+    let descriptor = hex::decode("045553444307865c6e87b9f70255377e024ace6630c1eaa37f00000006000000053045022100fde9f713cb999780a504b8eda31fe0195930935d8b0ad836e183b5c56b5e342d02202c15a0d1ad00b0dacae588524cf4db145934e10c8ba0e89da366e1793f723f70")?;
+    let mut signatures = (descriptor.len() as u32).to_be_bytes().to_vec();
+    signatures.extend_from_slice(&descriptor);
+
+    let mut db = AssetDatabase::default();
+    db.load_erc20_signatures(&signatures)?;
+    assert_eq!(1, db.tokens.len());
+    assert_eq!(5, db.tokens[0].chain_id);
+    assert_eq!(
+        "07865c6e87b9f70255377e024ace6630c1eaa37f",
+        db.tokens[0].contract_address
+    );
+
+    // USDC transfer(0x7562EF289fAf3554eEd27844B6473f165887cd40, 1000000) on goerli
+    let raw_tx = hex::decode("f86880830f4240830f42409407865c6e87b9f70255377e024ace6630c1eaa37f80b844a9059cbb0000000000000000000000007562ef289faf3554eed27844b6473f165887cd4000000000000000000000000000000000000000000000000000000000000f4240058080")?;
+    let resolution = db.resolve(&raw_tx)?;
+    assert_eq!(vec![hex::encode(&descriptor)], resolution.erc20_tokens);
+
+    // a truncated record is rejected
+    assert!(matches!(
+        db.load_erc20_signatures(&signatures[..signatures.len() - 1]),
+        Err(ResolverError::Descriptor(DescriptorError::Missing(
+            "descriptor"
+        )))
+    ));
+    Ok(())
+}
+
+#[test]
+fn can_resolve_with_cal_plugins() -> Result<()> {
+    let mut db = AssetDatabase::default();
+    db.load_plugins(
+        1,
+        r#"{
+            "0xdef171fe48cf0115b1d80b88dc8eab59176fee57": {
+                "abi": [],
+                "0x54e3f31b": {
+                    "erc20OfInterest": ["data.fromToken"],
+                    "plugin": "Paraswap",
+                    "serialized_data": "085061726173776170def171fe48cf0115b1d80b88dc8eab59176fee5754e3f31b",
+                    "signature": "3045022100aa"
+                }
+            }
+        }"#,
+    )?;
+    assert_eq!(1, db.external_plugins.len());
+
+    let raw_tx = TypedTx::from(Eip1559Tx {
+        chain_id: 1,
+        to: Some(
+            hex::decode("def171fe48cf0115b1d80b88dc8eab59176fee57")?
+                .try_into()
+                .unwrap(),
+        ),
+        data: hex::decode("54e3f31b")?,
+        ..Default::default()
+    })
+    .encode_unsigned();
+    let resolution = db.resolve(&raw_tx)?;
+    assert_eq!(1, resolution.external_plugins.len());
+    assert_eq!("3045022100aa", resolution.external_plugins[0].signature);
+
+    // NFT metadata service payloads
+    db.add_nft("010111426f7265644170655961636874436c7562bc4ca0eda7647a8ab7c2061c2e118a18a936f13d0000000000000001010104aaaaaaaa")?;
+    db.add_plugin("010106455243373231bc4ca0eda7647a8ab7c2061c2e118a18a936f13d42842e0e0000000000000001010104aaaaaaaa")?;
+    let raw_tx = TypedTx::from(LegacyTx {
+        chain_id: Some(1),
+        to: Some(
+            hex::decode("bc4ca0eda7647a8ab7c2061c2e118a18a936f13d")?
+                .try_into()
+                .unwrap(),
+        ),
+        data: hex::decode("42842e0e")?,
+        ..Default::default()
+    })
+    .encode_unsigned();
+    let resolution = db.resolve(&raw_tx)?;
+    assert_eq!(1, resolution.nfts.len());
+    assert_eq!(1, resolution.plugin.len());
+    Ok(())
+}
+
+#[test]
+fn can_resolve_nft_approval() -> Result<()> {
+    let db = AssetDatabase::from_json(
+        r#"{
+            "tokens": [{ "chain_id": 1, "contract_address": "0x1111111111111111111111111111111111111111", "payload": "00" }],
+            "nfts": [{ "chain_id": 1, "contract_address": "0xBC4CA0EdA7647A8aB7C2061c2E118A18a936f13D", "payload": "01" }],
+            "plugins": [{ "chain_id": 1, "contract_address": "0xBC4CA0EdA7647A8aB7C2061c2E118A18a936f13D", "selector": "0x095ea7b3", "payload": "02" }]
+        }"#,
+    )?;
+    // approve(0x7562EF289fAf3554eEd27844B6473f165887cd40, 1) on BAYC
+    let approve = |to: &str| -> Result<Vec<u8>> {
+        Ok(TypedTx::from(LegacyTx {
+            chain_id: Some(1),
+            to: Some(hex::decode(to)?.try_into().unwrap()),
+            data: hex::decode("095ea7b30000000000000000000000007562ef289faf3554eed27844b6473f165887cd400000000000000000000000000000000000000000000000000000000000000001")?,
+            ..Default::default()
+        })
+        .encode_unsigned())
+    };
+
+    let resolution = db.resolve(&approve("bc4ca0eda7647a8ab7c2061c2e118a18a936f13d")?)?;
+    assert_eq!(vec!["01".to_string()], resolution.nfts);
+    assert_eq!(vec!["02".to_string()], resolution.plugin);
+    assert!(resolution.erc20_tokens.is_empty());
+
+    // the same selector on a token contract is an ERC 20 approval
+    let resolution = db.resolve(&approve("1111111111111111111111111111111111111111")?)?;
+    assert_eq!(vec!["00".to_string()], resolution.erc20_tokens);
+    assert!(resolution.nfts.is_empty() && resolution.plugin.is_empty());
     Ok(())
 }